@@ -0,0 +1,167 @@
+use serde::{Serialize, Deserialize};
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config_manager::Settings;
+
+// Players tried in order when sending a sound to the speakers
+const AUDIO_PLAYERS: [&str; 3] = ["paplay", "aplay", "afplay"];
+
+const TONE_SAMPLE_RATE: u32 = 44_100;
+const TONE_FREQUENCY: f32 = 880.0;
+const TONE_SECONDS: f32 = 0.4;
+
+#[derive(Default, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertSound {
+    #[default]
+    Bell,
+    Tone,
+    Wav(PathBuf),
+}
+
+impl fmt::Display for AlertSound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bell => write!(f, "terminal bell"),
+            Self::Tone => write!(f, "tone"),
+            Self::Wav(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+// Where alerts end up. Null and File exist so the timer can run on machines without a terminal or sound card
+#[derive(Default, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertOutput {
+    #[default]
+    Speaker,
+    Null,
+    File(PathBuf),
+}
+
+pub struct Alert {
+    sound: AlertSound,
+    output: AlertOutput,
+    repeat_every: Option<Duration>,
+}
+
+impl Alert {
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self {
+            sound: settings.alert_sound.clone(),
+            output: settings.alert_output.clone(),
+            repeat_every: (settings.alert_repeat_seconds > 0)
+                .then(|| Duration::from_secs(settings.alert_repeat_seconds.into())),
+        }
+    }
+
+    pub fn repeat_every(&self) -> Option<Duration> {
+        self.repeat_every
+    }
+
+    // Best-effort: a missing player or unwritable file never interrupts the timer
+    pub fn play(&self) {
+        let _ = match &self.output {
+            AlertOutput::Speaker => self.play_on_speaker(),
+            AlertOutput::Null => Ok(()),
+            AlertOutput::File(path) => self.record_to_file(path),
+        };
+    }
+
+    fn play_on_speaker(&self) -> io::Result<()> {
+        let played = match &self.sound {
+            AlertSound::Bell => false,
+            AlertSound::Tone => write_tone_file().and_then(|path| play_wav(&path)).is_ok(),
+            AlertSound::Wav(path) => play_wav(path).is_ok(),
+        };
+        // Fall back to the bell so an alert is never silently lost
+        if !played {
+            ring_bell()?;
+        }
+        Ok(())
+    }
+
+    fn record_to_file(&self, path: &Path) -> io::Result<()> {
+        let secs = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{secs} {}", self.sound)
+    }
+}
+
+fn ring_bell() -> io::Result<()> {
+    let mut stdout = io::stdout();
+    stdout.write_all(b"\x07")?;
+    stdout.flush()
+}
+
+fn play_wav(path: &Path) -> io::Result<()> {
+    for player in AUDIO_PLAYERS {
+        let spawned = Command::new(player)
+            .arg(path)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        if let Ok(mut child) = spawned {
+            // Reap the player in the background so the timer thread never waits on audio
+            thread::spawn(move || child.wait());
+            return Ok(());
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::NotFound, "No audio player available"))
+}
+
+// The tone as a file the players can open. Anything already at the path is only reused if it is exactly our
+// tone, so neither a file cut short by a crash nor one left there by someone else gets played
+fn write_tone_file() -> io::Result<PathBuf> {
+    // One file shared by every run, so none are left behind
+    let path = std::env::temp_dir().join("rustypomodoro-tone.wav");
+    let tone = generate_tone();
+    if fs::read(&path).is_ok_and(|contents| contents == tone) {
+        return Ok(path);
+    }
+    // Written under a name of our own, so instances replacing it at once don't get in each other's way
+    let temp_path = path.with_extension(format!("wav.tmp-{}", std::process::id()));
+    let _ = fs::remove_file(&temp_path);
+    // create_new, so a link planted at the temporary path is never followed
+    let mut file = OpenOptions::new().write(true).create_new(true).open(&temp_path)?;
+    file.write_all(&tone)?;
+    file.sync_all()?;
+    fs::rename(&temp_path, &path)?;
+    Ok(path)
+}
+
+// Short 16-bit mono sine beep as a complete WAV file, faded in and out to avoid clicks
+fn generate_tone() -> Vec<u8> {
+    let num_samples = (TONE_SAMPLE_RATE as f32 * TONE_SECONDS) as u32;
+    let fade_samples = num_samples / 10;
+    let data_len = num_samples * 2;
+
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&1u16.to_le_bytes()); // Mono
+    wav.extend_from_slice(&TONE_SAMPLE_RATE.to_le_bytes());
+    wav.extend_from_slice(&(TONE_SAMPLE_RATE * 2).to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+
+    for i in 0..num_samples {
+        let t = i as f32 / TONE_SAMPLE_RATE as f32;
+        let envelope = (i.min(num_samples - i) as f32 / fade_samples as f32).min(1.0);
+        let sample = (t * TONE_FREQUENCY * std::f32::consts::TAU).sin() * envelope * 0.5;
+        wav.extend_from_slice(&((sample * i16::MAX as f32) as i16).to_le_bytes());
+    }
+    wav
+}
@@ -1,11 +1,40 @@
-use std::sync::mpsc::TryRecvError;
+use std::io;
+use std::path::PathBuf;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::{Duration, Instant};
 
-use crate::config_manager::{ConfigManager, Settings};
-use crate::timer::{Timer, TimerCommand, TimerEvent, TimerHandle};
+use crate::clock::SystemClock;
+use crate::config_layers::Override;
+use crate::config_manager::{ConfigError, ConfigManager, Settings};
+use crate::config_watcher::ConfigWatcher;
+use crate::history::History;
+#[cfg(feature = "notifications")]
+use crate::notify::Notifier;
+use crate::snapshot::{Snapshots, TimerSnapshot};
+use crate::timer::{Timer, TimerCommand, TimerEvent, TimerHandle, TimerSession, TimerState};
+
+// How often a running timer's snapshot is rewritten when nothing but the remaining time has changed
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(5);
 
 pub struct PomodoroApp {
     config: ConfigManager,
     timer_handle: Option<TimerHandle>,
+    history: Option<History>,
+    snapshots: Option<Snapshots>,
+    // The snapshot last written, and when
+    saved_snapshot: Option<(TimerSnapshot, Instant)>,
+    // Last problem loading or saving the config, until a save succeeds
+    config_error: Option<ConfigError>,
+    // Task that work sessions are recorded against
+    current_task: Option<u32>,
+    // Set once the app should follow changes made to the config file while running
+    config_watcher: Option<ConfigWatcher>,
+    // Whether the running timer uses the saved settings, rather than one-off ones
+    timer_follows_config: bool,
+    // Time a snooze adds to the running timer's session
+    snooze: Duration,
+    #[cfg(feature = "notifications")]
+    notifier: Option<Notifier>,
 }
 
 impl PomodoroApp {
@@ -13,25 +42,168 @@ impl PomodoroApp {
         Self {
             config: ConfigManager::new(),
             timer_handle: None,
+            history: None,
+            snapshots: None,
+            saved_snapshot: None,
+            config_error: None,
+            current_task: None,
+            config_watcher: None,
+            timer_follows_config: false,
+            snooze: Duration::ZERO,
+            #[cfg(feature = "notifications")]
+            notifier: None,
         }
     }
 
-    pub fn init(&mut self) {
-        self.config.build();
+    // Load the config from `config_path`, or the usual place if not given, with `overrides` over it
+    pub fn init(&mut self, config_path: Option<PathBuf>, overrides: Vec<Override>) {
+        // A broken config leaves us with usable settings, the error is kept to show the user
+        self.config_error = self.config.build(config_path, overrides).err();
+        // Without a data directory sessions just go unrecorded
+        self.history = History::open_default().ok();
+        self.snapshots = Snapshots::open_default().ok();
     }
 
     pub fn get_settings(&self) -> &Settings {
         self.config.get_settings()
     }
 
-    pub fn save_config(&mut self, new_settings: Settings) {
-        *self.config.get_mut_settings() = new_settings;
-        self.config.save();
+    pub fn config_error(&self) -> Option<&ConfigError> {
+        self.config_error.as_ref()
+    }
+
+    // Override variables in the environment that were left out. Nothing saving the config can fix
+    pub fn env_error(&self) -> Option<ConfigError> {
+        self.config.env_error()
+    }
+
+    pub fn save_config(&mut self, new_settings: Settings) -> Result<(), ConfigError> {
+        new_settings.validate()?;
+        self.config.set_settings(new_settings);
+        let result = self.config.save();
+        if result.is_ok() {
+            self.config_error = None;
+        }
+        result
+    }
+
+    // Follow changes made to the config file from now on
+    pub fn watch_config(&mut self) {
+        let paths = self.config.watched_paths();
+        if !paths.is_empty() {
+            self.config_watcher = Some(ConfigWatcher::spawn(paths));
+        }
+    }
+
+    // Reload the config if the file has changed. The running timer gets the new settings too, unless it
+    // was started with one-off ones. Returns whether anything changed
+    pub fn reload_config_if_changed(&mut self) -> bool {
+        if !self.config_watcher.as_ref().is_some_and(|watcher| watcher.has_changed()) {
+            return false;
+        }
+        match self.config.reload() {
+            Ok(changed) => {
+                let had_error = self.config_error.take().is_some();
+                if changed && self.timer_follows_config
+                    && let Some(handle) = &self.timer_handle {
+                    let settings = Box::new(self.config.get_settings().clone());
+                    let _ = handle.cmd_tx.send(TimerCommand::Reconfigure(settings));
+                }
+                changed || had_error
+            }
+            Err(e) => {
+                self.config_error = Some(e);
+                true
+            }
+        }
+    }
+
+    // Profile management goes straight to the config manager
+    pub fn config(&self) -> &ConfigManager {
+        &self.config
+    }
+
+    pub fn config_mut(&mut self) -> &mut ConfigManager {
+        &mut self.config
+    }
+
+    // Use these settings for this run only, without saving them
+    pub fn override_settings(&mut self, settings: Settings) {
+        self.config.use_for_run(settings);
+    }
+
+    // Record sessions somewhere other than the data directory
+    #[cfg(test)]
+    pub fn set_history(&mut self, history: History) {
+        self.history = Some(history);
+    }
+
+    pub fn set_current_task(&mut self, task: Option<u32>) {
+        self.current_task = task;
     }
 
     pub fn start_timer(&mut self) {
-        self.timer_handle = Some(Timer::spawn(self.config.get_settings().clone()));
-    } 
+        self.start_timer_with(self.config.get_settings().clone());
+        self.timer_follows_config = true;
+    }
+
+    // Start a timer with one-off settings, leaving the app's settings untouched
+    pub fn start_timer_with(&mut self, settings: Settings) {
+        self.timer_follows_config = false;
+        let snooze = Duration::from_secs(settings.snooze_seconds.into());
+        self.attach_timer(Timer::spawn(settings, SystemClock), snooze);
+    }
+
+    fn attach_timer(&mut self, handle: TimerHandle, snooze: Duration) {
+        #[cfg(feature = "notifications")]
+        self.attach_notifier(&handle, snooze);
+        self.snooze = snooze;
+        self.timer_handle = Some(handle);
+    }
+
+    // The session an earlier run left unfinished, if there is one
+    pub fn interrupted_session(&self) -> Option<TimerSnapshot> {
+        self.snapshots.as_ref()?.interrupted()
+    }
+
+    // Carry on with an interrupted session, on the settings and task it was started with
+    pub fn resume_interrupted(&mut self, snapshot: TimerSnapshot) {
+        self.current_task = snapshot.task;
+        let follows_config = snapshot.settings == *self.config.get_settings();
+        let snooze = Duration::from_secs(snapshot.settings.snooze_seconds.into());
+        self.attach_timer(Timer::spawn_resumed(snapshot, SystemClock), snooze);
+        self.timer_follows_config = follows_config;
+    }
+
+    pub fn discard_interrupted(&mut self) {
+        if let Some(snapshots) = &mut self.snapshots {
+            snapshots.clear();
+        }
+    }
+
+    // Log an interrupted session to history as it stood, then discard it
+    pub fn log_interrupted(&mut self, snapshot: &TimerSnapshot) -> io::Result<()> {
+        if snapshot.is_unrecorded() && let Some(history) = &self.history {
+            history.append(&snapshot.session_record())?;
+        }
+        self.discard_interrupted();
+        Ok(())
+    }
+
+    #[cfg(feature = "notifications")]
+    fn attach_notifier(&mut self, handle: &TimerHandle, snooze: Duration) {
+        if !self.config.get_settings().desktop_notifications {
+            self.notifier = None;
+            return;
+        }
+        // Keep one connection for the life of the app. Without a session bus we just go without
+        if self.notifier.is_none() {
+            self.notifier = Notifier::connect().ok();
+        }
+        if let Some(notifier) = &mut self.notifier {
+            notifier.attach(handle.cmd_tx.clone(), snooze);
+        }
+    }
 
     pub fn pause_timer(&self) {
         if let Some(handle) = &self.timer_handle {
@@ -57,14 +229,48 @@ impl PomodoroApp {
         }
     }
 
+    pub fn skip_session(&self) {
+        if let Some(handle) = &self.timer_handle {
+            let _ = handle.cmd_tx.send(TimerCommand::Skip);
+        }
+    }
+
+    pub fn restart_session(&self) {
+        if let Some(handle) = &self.timer_handle {
+            let _ = handle.cmd_tx.send(TimerCommand::RestartSession);
+        }
+    }
+
+    pub fn snooze_timer(&self) {
+        if let Some(handle) = &self.timer_handle {
+            let _ = handle.cmd_tx.send(TimerCommand::Extend(self.snooze));
+        }
+    }
+
     pub fn poll_timer_event(&mut self) -> Option<TimerEvent> {
+        self.wait_timer_event(Duration::ZERO)
+    }
+
+    // Like poll_timer_event, but give the timer up to `timeout` to produce an event
+    pub fn wait_timer_event(&mut self, timeout: Duration) -> Option<TimerEvent> {
+        self.reload_config_if_changed();
+        self.record_finished_sessions();
+        self.save_snapshot();
         if let Some(handle) = &self.timer_handle {
-            match handle.evt_rx.try_recv() {
-                Ok(evt) => Some(evt),
-                Err(TryRecvError::Empty) => None,
-                Err(TryRecvError::Disconnected) => {
+            match handle.evt_rx.recv_timeout(timeout) {
+                Ok(evt) => {
+                    #[cfg(feature = "notifications")]
+                    if let Some(notifier) = &mut self.notifier {
+                        notifier.handle_event(&evt);
+                    }
+                    Some(evt)
+                },
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => {
                     // If we are done with the timer
+                    self.record_finished_sessions();
                     self.timer_handle = None;
+                    self.clear_snapshot();
                     None
                 }
             }
@@ -73,7 +279,45 @@ impl PomodoroApp {
         }
     }
 
+    // Best-effort: failing to write history never interrupts the timer
+    fn record_finished_sessions(&self) {
+        if let (Some(handle), Some(history)) = (&self.timer_handle, &self.history) {
+            for mut record in handle.record_rx.try_iter() {
+                if record.session == TimerSession::Working {
+                    record.task = self.current_task;
+                }
+                let _ = history.append(&record);
+            }
+        }
+    }
+
+    // Keep the latest snapshot on disk. Best-effort like history: without it a crash just loses the session
+    fn save_snapshot(&mut self) {
+        let Some(mut snapshot) = self.timer_handle.as_ref().and_then(|handle| handle.snapshot_rx.try_iter().last()) else {
+            return;
+        };
+        if snapshot.state == TimerState::Idle {
+            self.clear_snapshot();
+            return;
+        }
+        snapshot.task = self.current_task;
+        // Changes of state or session are written straight away, the countdown only every so often
+        let due = self.saved_snapshot.as_ref().is_none_or(|(saved, at)| {
+            (saved.state, saved.segment_index, saved.started_at) != (snapshot.state, snapshot.segment_index, snapshot.started_at)
+                || at.elapsed() >= SNAPSHOT_INTERVAL
+        });
+        if due && let Some(snapshots) = &mut self.snapshots
+            && snapshots.save(&snapshot).is_ok() {
+            self.saved_snapshot = Some((snapshot, Instant::now()));
+        }
+    }
+
+    fn clear_snapshot(&mut self) {
+        self.saved_snapshot = None;
+        self.discard_interrupted();
+    }
+
     pub fn is_timer_disconnected(&self) -> bool {
-        if let None = self.timer_handle {true} else {false}
+        self.timer_handle.is_none()
     }
 }
\ No newline at end of file
@@ -8,35 +8,170 @@ It will handle all the user interace (text prompts) allowing the user to:
 - Edit configuration data
 It does not actually perform these actions, rather passes the instruction to the relevant service.
 */
+use crate::alert::AlertSound;
+use crate::config_manager::{ConfigError, Segment, Settings};
 use crate::utils;
 use crate::PomodoroApp;
 use crate::queryOptions;
-use crate::timer::{TimerState, TimerSession};
-use crate::utils::{clear_terminal, poll_user_input};
+use crate::report;
+use crate::snapshot::TimerSnapshot;
+use crate::tasks::{self, TaskList, TaskStatus};
+use crate::timer::{SuspendPolicy, TimerState, TimerSession};
+use crate::utils::{clear_terminal, get_display_time, poll_user_input};
 use crossterm::event::KeyCode::{self,Char};
 
 
 pub fn run(app: &mut PomodoroApp) {
+    app.watch_config();
+    if let Some(snapshot) = app.interrupted_session() {
+        cli_recover_session(app, snapshot);
+    }
     loop {
+        app.reload_config_if_changed();
         let settings = app.get_settings();
 
         utils::clear_terminal();
 
         println!("POMODORO TIMER\n");
         println!("Welcome to this pomodoro timer, modified for eye strain management.\n");
-        println!("Your current settings are as such:");
+        if app.config_error().is_some() {
+            println!("⚠ There is a problem with your settings file, see Edit Settings.\n");
+        }
+        if let Some(e) = app.env_error() {
+            println!("⚠ {e}\n");
+        }
+        println!("Your current settings ({} profile) are as such:", app.config().active_profile());
         println!("{settings}\n");
-        let option = queryOptions!("Options:","Start Timer", "Edit Settings", "Exit 🚪");
+        let option = queryOptions!("Options:","Start Timer", "Edit Settings", "Tasks 📋", "View Report 📊", "Exit 🚪");
 
         match option {
-            1 => {if let Err(_)  = cli_run_timer(app) {
+            1 => {if cli_run_timer(app, cli_choose_task()).is_err() {
                 println!("Something went wrong when trying to start the timer. Try again!");
                 println!("If the error persists try contacting an admin\n");
             }},
             2 => cli_edit_settings(app),
-            3 => break,
+            3 => cli_manage_tasks(),
+            4 => cli_view_report(),
+            5 => break,
+            _ => unreachable!("User was somehow able to chose an invalid option"),
+        };
+    }
+}
+
+// Offer to pick up a session that was still running when the app last closed. Returns whether it was resumed
+pub fn cli_recover_session(app: &mut PomodoroApp, snapshot: TimerSnapshot) -> bool {
+    utils::clear_terminal();
+    println!("INTERRUPTED SESSION\n");
+    println!("The timer was still running when the app last closed:");
+    println!("{}\n", snapshot.describe());
+    let mut options = vec!["Resume it", "Discard it"];
+    if snapshot.is_unrecorded() {
+        options.push("Log it to history");
+    }
+
+    match utils::query_user_option("What would you like to do?", &options) {
+        1 => {
+            let total_cycles = snapshot.settings.total_cycles();
+            app.resume_interrupted(snapshot);
+            if cli_follow_timer(app, total_cycles).is_err() {
+                println!("Something went wrong when trying to resume the timer.\n");
+            }
+            return true;
+        }
+        2 => app.discard_interrupted(),
+        3 => if let Err(e) = app.log_interrupted(&snapshot) {
+            println!("Could not log the session: {e}\n");
+            utils::get_input("Press Enter to continue");
+        },
+        _ => unreachable!("User was somehow able to chose an invalid option"),
+    }
+    false
+}
+
+fn cli_view_report() {
+    utils::clear_terminal();
+    let (from, to) = report::last_days(7);
+    match report::load(from, to) {
+        Ok(report) => println!("{}", report::render(&report)),
+        Err(e) => println!("Could not build the report: {e}\n"),
+    }
+    utils::get_input("Press Enter to go back");
+}
+
+// Ask which task the coming work sessions are for. None if the user works without one
+fn cli_choose_task() -> Option<u32> {
+    let mut list = match TaskList::open_default() {
+        Ok(list) => list,
+        Err(e) => {
+            println!("Could not load tasks, continuing without one: {e}");
+            return None;
+        }
+    };
+    let open: Vec<(u32, String)> = list.open_tasks().map(|task| (task.id, task.name.clone())).collect();
+
+    utils::clear_terminal();
+    println!("CHOOSE TASK\n");
+    let mut options: Vec<&str> = open.iter().map(|(_, name)| name.as_str()).collect();
+    options.extend(["New task ➕", "No task"]);
+    let option = utils::query_user_option("What are you working on?", &options) as usize;
+
+    if option <= open.len() {
+        return Some(open[option - 1].0);
+    }
+    if option == open.len() + 1 {
+        let name = utils::get_input("\nName of the new task:");
+        let created = list.create(&name, None).and_then(|id| list.save().map(|_| id));
+        match created {
+            Ok(id) => return Some(id),
+            Err(e) => println!("Could not create the task: {e}"),
+        }
+    }
+    None
+}
+
+fn cli_manage_tasks() {
+    let mut list = match TaskList::open_default() {
+        Ok(list) => list,
+        Err(e) => {
+            println!("Could not load tasks: {e}\n");
+            utils::get_input("Press Enter to go back");
+            return;
+        }
+    };
+    let actuals = tasks::recorded_actuals();
+
+    loop {
+        utils::clear_terminal();
+        println!("TASKS\n");
+        println!("{}", tasks::render_table(list.open_tasks(), &actuals));
+
+        let option = queryOptions!("Options:", "New task", "Rename task", "Complete task", "Archive task",
+            "Estimate task", "Back 🚪");
+        let result = match option {
+            1 => {
+                let name = utils::get_input("\nName of the new task:");
+                let estimate = utils::get_uint_input("\nEstimated pomodoros (0 for none):");
+                list.create(&name, Some(estimate).filter(|e| *e > 0)).map(|_| ())
+            }
+            2 => {
+                let id = utils::get_uint_input("\nID of the task to rename:");
+                let name = utils::get_input("\nNew name:");
+                list.rename(id, &name)
+            }
+            3 => list.set_status(utils::get_uint_input("\nID of the completed task:"), TaskStatus::Completed),
+            4 => list.set_status(utils::get_uint_input("\nID of the task to archive:"), TaskStatus::Archived),
+            5 => {
+                let id = utils::get_uint_input("\nID of the task to estimate:");
+                let estimate = utils::get_uint_input("\nEstimated pomodoros (0 for none):");
+                list.set_estimate(id, Some(estimate).filter(|e| *e > 0))
+            }
+            6 => break,
             _ => unreachable!("User was somehow able to chose an invalid option"),
         };
+        if let Err(e) = result.and_then(|_| list.save()) {
+            println!("{e}");
+            utils::get_input("Press Enter to continue");
+        }
     }
 }
 
@@ -48,20 +183,32 @@ impl Drop for RawModeGuard {
     }
 }
 
-fn cli_run_timer(app: &mut PomodoroApp) -> Result<(), std::io::Error> {
+// Run the timer with work sessions recorded against `task`, if there is one
+pub fn cli_run_timer(app: &mut PomodoroApp, task: Option<u32>) -> Result<(), std::io::Error> {
     // When we start timer:
+    //      Set the current task
     //      Start app timer     
     //      Get timer info
     //      Display UI
+    app.set_current_task(task);
 
+    let total_cycles = app.get_settings().total_cycles();
+    app.start_timer();
+    cli_follow_timer(app, total_cycles)
+}
+
+// Show the running timer and pass on the user's commands until it stops
+fn cli_follow_timer(app: &mut PomodoroApp, total_cycles: u32) -> Result<(), std::io::Error> {
     // For input 
     enable_raw_mode()?;
     let _guard = RawModeGuard;
 
-    let total_cycles = app.get_settings().work_relief_cycles;
-    app.start_timer();
     let mut current_state = TimerState::Idle;
     let mut has_drawn_waiting = false;
+    // What became of time the timer missed (e.g. asleep), shown until the state next changes
+    let mut gap_note: Option<(TimerState, String)> = None;
+    // Stopping mid-session throws the session away, so it takes a second press
+    let mut confirming_stop = false;
 
     loop {
         if let Some(event) = app.poll_timer_event() {
@@ -69,17 +216,35 @@ fn cli_run_timer(app: &mut PomodoroApp) -> Result<(), std::io::Error> {
             if matches!(event.state, TimerState::Idle) {
                 break;
             }
+            if let Some(gap) = event.gap {
+                gap_note = Some((event.state, gap.describe()));
+                has_drawn_waiting = false;
+            } else if gap_note.as_ref().is_some_and(|(state, _)| *state != event.state) {
+                gap_note = None;
+            }
 
-            // We dont want to draw multiple times if waiting
-            if !(matches!(event.state, TimerState::Waiting | TimerState::Paused) && has_drawn_waiting) {
+            // We dont want to draw multiple times if waiting, unless a grace countdown is running
+            if !(matches!(event.state, TimerState::Waiting | TimerState::Paused) && has_drawn_waiting) 
+                || event.auto_advance_in.is_some() {
                 has_drawn_waiting = true;
                 display_timer(
                     event.session, 
+                    &event.segment,
                     event.state, 
                     event.remaining, 
                     event.cycles_complete,
-                    total_cycles
+                    total_cycles,
+                    event.auto_advance_in
                 );
+                if event.snoozes_left > 0 && matches!(event.state, TimerState::CountDown | TimerState::Waiting) {
+                    println!("Press 3 to Snooze ({} left)", event.snoozes_left);
+                }
+                if let Some((_, note)) = &gap_note {
+                    println!("\n{note}");
+                }
+                if confirming_stop {
+                    println!("{STOP_PROMPT}");
+                }
             }   
             if !matches!(event.state, TimerState::Waiting | TimerState::Paused) {has_drawn_waiting=false}
 
@@ -89,7 +254,19 @@ fn cli_run_timer(app: &mut PomodoroApp) -> Result<(), std::io::Error> {
 
         // Handle input
         if let Some(input) = poll_user_input() {
-            handle_input(app, current_state, input);
+            if confirming_stop {
+                confirming_stop = false;
+                // Paused screens are only drawn once, so draw it again without the prompt
+                has_drawn_waiting = false;
+                if input == Char('2') {
+                    app.stop_timer();
+                }
+            } else if current_state.is_session_under_way() && input == Char('2') {
+                confirming_stop = true;
+                println!("{STOP_PROMPT}");
+            } else {
+                handle_input(app, current_state, input);
+            }
         }
 
         std::thread::sleep(std::time::Duration::from_millis(30));
@@ -98,31 +275,32 @@ fn cli_run_timer(app: &mut PomodoroApp) -> Result<(), std::io::Error> {
     Ok(())
 }
 
-fn display_timer(session: TimerSession, state: TimerState, time_remaining: u32, cycles: u32, total_cycles: u32) {
+fn display_timer(session: TimerSession, segment: &str, state: TimerState, time_remaining: u32, cycles: u32, total_cycles: u32, auto_advance_in: Option<u32>) {
         clear_terminal();
         println!("TIMER\n");
 
         println!("[{}]", state.as_str());
-        println!("Session: {} \n", session.as_str());
+        println!("Session: {} ({}) \n", segment, session.as_str());
 
         println!("Cycle {}/{}", cycles+1, total_cycles);
         println!("Time Remaining:");
         println!("{}",get_display_time(time_remaining));
 
+        if let Some(grace) = auto_advance_in {
+            println!("\nNext session starts automatically in {grace}s");
+        }
+
         // Display the correct commands
         println!("{} (Then Enter to submit command)", get_display_commands(state));  
 }
 
-fn get_display_time(time_seconds: u32) -> String {
-    // Rust int division always truncates
-    format!("{:02}:{:02}", (time_seconds/60), time_seconds%60)
-}
+const STOP_PROMPT: &str = "\nStop the timer? The session so far is recorded as aborted. Press 2 again to stop, anything else to carry on";
 
 fn get_display_commands(state: TimerState) -> &'static str {
     match state {
-        TimerState::CountDown => "Press 1 to Pause",
-        TimerState::Paused => "Press 1 to Resume, Press 2 to Exit",
-        TimerState::Waiting => "Press 1 to advance session, Press 2 to Exit",
+        TimerState::CountDown => "Press 1 to Pause, Press 2 to Exit, Press 4 to Skip, Press 5 to Restart",
+        TimerState::Paused => "Press 1 to Resume, Press 2 to Exit, Press 4 to Skip, Press 5 to Restart",
+        TimerState::Waiting => "Press 1 to advance session, Press 2 to Exit, Press 4 to Skip the next session, Press 5 to Restart",
         TimerState::Idle => "Returning to Main Menu"
     }
 }
@@ -134,6 +312,9 @@ fn handle_input(app: &PomodoroApp, state: TimerState, input: KeyCode) {
         (TimerState::Paused, Char('2')) => app.stop_timer(),
         (TimerState::Waiting, Char('1')) => app.advance_timer(),
         (TimerState::Waiting, Char('2')) => app.stop_timer(),
+        (TimerState::CountDown | TimerState::Waiting, Char('3')) => app.snooze_timer(),
+        (TimerState::CountDown | TimerState::Paused | TimerState::Waiting, Char('4')) => app.skip_session(),
+        (TimerState::CountDown | TimerState::Paused | TimerState::Waiting, Char('5')) => app.restart_session(),
         _ => {/* Do Nothing if unrecognised command */}
     }
 }
@@ -144,28 +325,187 @@ fn cli_edit_settings(app: &mut PomodoroApp) {
     //      Wait for user input -> multiple menus
     //      call app to save        <APP
     //      Go back to main menu
-    let mut new_settings = app.get_settings().clone();
+    // Start from the saved settings even if they don't validate, so only the flagged ones need fixing
+    let mut new_settings = app.config().settings_to_edit();
+    // Why the last save failed, shown until the next attempt
+    let mut save_error: Option<String> = None;
 
     loop {
         utils::clear_terminal();
         println!("CONFIGURE SETTINGS\n");
 
-        println!("Your current settings are as such:");
+        if let Some(e) = &save_error {
+            println!("⚠ Your settings could not be saved: {e}\n");
+        } else if let Some(ConfigError::Invalid(_)) = app.config_error() {
+            println!("⚠ The saved settings are not valid, so the defaults are in use until the settings flagged below are fixed and saved.\n");
+        } else if let Some(e) = app.config_error() {
+            println!("⚠ {e}");
+            println!("The settings below are in use instead. Saving writes them to the settings file.\n");
+        }
+        if let Some(e) = app.env_error() {
+            println!("⚠ {e}\n");
+        }
+
+        println!("Your current settings ({} profile) are as such:", app.config().active_profile());
         println!("{new_settings}\n");
+        let violations = new_settings.violations();
+        if !violations.is_empty() {
+            println!("⚠ These settings can't be saved until this is fixed:");
+            for violation in &violations {
+                println!("  - {violation}");
+            }
+            println!();
+        }
 
-        let option = queryOptions!("Options:", "Work time", "Relief time", "Break time", "no. Cycles", "Save and Exit 💾", "Back 🚪");
+        let option = queryOptions!("Options:", "Work time", "Relief time", "Break time", "no. Cycles", 
+            "Toggle auto-start work", "Toggle auto-start relief", "Toggle auto-start break", "Auto-start delay",
+            "Alert sound", "Alert repeat", "Snooze length", "Snoozes per session", "Toggle desktop notifications",
+            "After sleep", "Custom sequence", "Profiles", "Save and Exit 💾", "Back 🚪");
         match option {
-            1 => new_settings.work_seconds = utils::get_posint_input("\nSet work timer in minutes:") * 60,
+            1 => new_settings.work_seconds = utils::get_posint_input("\nSet work timer in minutes:").saturating_mul(60),
             2 => new_settings.relief_seconds = utils::get_posint_input("\nSet relief timer in seconds:"),
-            3 => new_settings.break_seconds = utils::get_posint_input("\nSet break timer in minutes:") * 60,
+            3 => new_settings.break_seconds = utils::get_posint_input("\nSet break timer in minutes:").saturating_mul(60),
             4 => new_settings.work_relief_cycles = utils::get_posint_input("\nSet number of cycles (no. work-relief sessions before break):"),
-            5 => {
-                // Save and Exit option
-                app.save_config(new_settings);
-                break;
+            5 => new_settings.auto_start_work = !new_settings.auto_start_work,
+            6 => new_settings.auto_start_relief = !new_settings.auto_start_relief,
+            7 => new_settings.auto_start_break = !new_settings.auto_start_break,
+            8 => new_settings.auto_advance_grace_seconds = utils::get_uint_input("\nSet delay before auto-starting the next session in seconds:"),
+            9 => new_settings.alert_sound = cli_choose_alert_sound(),
+            10 => new_settings.alert_repeat_seconds = utils::get_uint_input("\nReplay the alert every how many seconds while waiting (0 to play once):"),
+            11 => new_settings.snooze_seconds = utils::get_posint_input("\nSet snooze length in minutes:").saturating_mul(60),
+            12 => new_settings.max_snoozes = utils::get_uint_input("\nSet how many times a session can be snoozed (0 to turn snoozing off):"),
+            13 => new_settings.desktop_notifications = !new_settings.desktop_notifications,
+            14 => new_settings.suspend_policy = cli_choose_suspend_policy(),
+            15 => cli_edit_sequence(&mut new_settings),
+            16 => {
+                cli_manage_profiles(app);
+                // Carry on editing whichever profile is now active
+                new_settings = app.config().settings_to_edit();
+            }
+            17 => {
+                // Save and Exit option. On failure stay here, so the edits aren't lost
+                match app.save_config(new_settings.clone()) {
+                    Ok(()) => break,
+                    Err(e) => save_error = Some(e.to_string()),
+                }
             },
+            18 => break,
+            _ => unreachable!("User was somehow able to chose an invalid option"),
+        }
+    }
+}
+
+fn cli_manage_profiles(app: &mut PomodoroApp) {
+    loop {
+        utils::clear_terminal();
+        println!("PROFILES\n");
+        println!("Changing profile drops any unsaved changes to the settings.\n");
+        let config = app.config();
+        for name in config.profile_names() {
+            let marker = if name == config.active_profile() { "(active)" } else { "" };
+            println!("- {name} {marker}");
+        }
+        println!();
+
+        let option = queryOptions!("Options:", "Switch profile", "New profile", "Clone profile", "Rename profile",
+            "Delete profile", "Back 🚪");
+        let config = app.config_mut();
+        let result = match option {
+            1 => config.switch_profile(&utils::get_input("\nProfile to switch to:")),
+            2 => config.create_profile(&utils::get_input("\nName of the new profile:")),
+            3 => {
+                let from = utils::get_input("\nProfile to clone:");
+                config.clone_profile(&from, &utils::get_input("\nName of the copy:"))
+            }
+            4 => {
+                let from = utils::get_input("\nProfile to rename:");
+                config.rename_profile(&from, &utils::get_input("\nNew name:"))
+            }
+            5 => config.delete_profile(&utils::get_input("\nProfile to delete:")),
             6 => break,
             _ => unreachable!("User was somehow able to chose an invalid option"),
+        };
+        if let Err(e) = result {
+            println!("{e}");
+            utils::get_input("Press Enter to continue");
+        }
+    }
+}
+
+fn cli_edit_sequence(settings: &mut Settings) {
+    loop {
+        utils::clear_terminal();
+        println!("SESSION SEQUENCE\n");
+        if settings.sequence.is_empty() {
+            println!("Using the default work, relief and break settings:");
+        }
+        for (i, segment) in settings.sequence().iter().enumerate() {
+            println!("{}. {segment}", i + 1);
+        }
+        println!();
+
+        let option = queryOptions!("Options:", "Add segment", "Remove segment", "Use default sequence", "Back 🚪");
+        match option {
+            1 => {
+                // Start a custom sequence from what is in use now, so adding a step doesn't lose the rest
+                if settings.sequence.is_empty() {
+                    settings.sequence = settings.sequence();
+                }
+                settings.sequence.push(cli_new_segment());
+            }
+            2 => {
+                if settings.sequence.is_empty() {
+                    settings.sequence = settings.sequence();
+                }
+                let position = utils::get_posint_input("\nNumber of the segment to remove:") as usize;
+                if position <= settings.sequence.len() && settings.sequence.len() > 1 {
+                    settings.sequence.remove(position - 1);
+                }
+            }
+            3 => settings.sequence.clear(),
+            4 => break,
+            _ => unreachable!("User was somehow able to chose an invalid option"),
+        }
+    }
+}
+
+fn cli_new_segment() -> Segment {
+    let name = utils::get_input("\nSegment name:");
+    let session = match queryOptions!("\nSegment type:", "Work", "Eye relief", "Break") {
+        1 => TimerSession::Working,
+        2 => TimerSession::Resting,
+        3 => TimerSession::Break,
+        _ => unreachable!("User was somehow able to chose an invalid option"),
+    };
+    let seconds = loop {
+        match utils::parse_duration(&utils::get_input("\nLength, e.g. 25m or 20s:")) {
+            Ok(seconds) if seconds > 0 => break seconds,
+            Ok(_) => println!("Please enter a length above zero!"),
+            Err(e) => println!("{e}"),
         }
+    };
+    let auto_advance = queryOptions!("\nStart this segment automatically?", "Yes", "No") == 1;
+    let name = if name.is_empty() { session.as_str() } else { &name };
+    Segment::new(name, session, seconds, auto_advance)
+}
+
+fn cli_choose_alert_sound() -> AlertSound {
+    let option = queryOptions!("\nAlert sound:", "Terminal bell 🔔", "Generated tone", "WAV file");
+    match option {
+        1 => AlertSound::Bell,
+        2 => AlertSound::Tone,
+        3 => AlertSound::Wav(utils::get_input("\nPath to WAV file:").into()),
+        _ => unreachable!("User was somehow able to chose an invalid option"),
+    }
+}
+
+fn cli_choose_suspend_policy() -> SuspendPolicy {
+    let option = queryOptions!("\nWhen the computer sleeps during a session:", "Count the sleep as a break",
+        "Pause the session", "Keep counting the session");
+    match option {
+        1 => SuspendPolicy::Break,
+        2 => SuspendPolicy::Pause,
+        3 => SuspendPolicy::Count,
+        _ => unreachable!("User was somehow able to chose an invalid option"),
     }
 }
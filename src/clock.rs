@@ -0,0 +1,98 @@
+use crate::timer::TimerCommand;
+
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Instant, SystemTime};
+
+// Source of time for the timer thread. Lets the state machine run against real time or virtual time in tests
+pub trait Clock: Send + 'static {
+    fn now(&self) -> Instant;
+
+    // Wall clock time, for timestamps that mean something outside this process
+    fn system_now(&self) -> SystemTime;
+
+    // Block until a command arrives or the clock reaches the deadline (forever if there is none)
+    fn recv_until(&self, rx: &Receiver<TimerCommand>, deadline: Option<Instant>) -> Result<TimerCommand, RecvTimeoutError>;
+}
+
+#[derive(Copy, Clone, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn system_now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn recv_until(&self, rx: &Receiver<TimerCommand>, deadline: Option<Instant>) -> Result<TimerCommand, RecvTimeoutError> {
+        match deadline {
+            Some(deadline) => rx.recv_timeout(deadline.saturating_duration_since(Instant::now())),
+            None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        }
+    }
+}
+
+#[cfg(test)]
+pub use manual::ManualClock;
+
+#[cfg(test)]
+mod manual {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    // Clock that only moves when told to. Clones share the same virtual time
+    #[derive(Clone)]
+    pub struct ManualClock {
+        start: Instant,
+        system_start: SystemTime,
+        elapsed: Arc<Mutex<Duration>>,
+        // Wall time that passed without the monotonic clock, as it does while the machine sleeps
+        asleep: Arc<Mutex<Duration>>,
+    }
+
+    impl ManualClock {
+        pub fn new() -> Self {
+            Self {
+                start: Instant::now(),
+                system_start: SystemTime::now(),
+                elapsed: Arc::new(Mutex::new(Duration::ZERO)),
+                asleep: Arc::new(Mutex::new(Duration::ZERO)),
+            }
+        }
+
+        pub fn advance(&self, by: Duration) {
+            *self.elapsed.lock().unwrap() += by;
+        }
+
+        pub fn sleep(&self, by: Duration) {
+            *self.asleep.lock().unwrap() += by;
+        }
+    }
+
+    impl Clock for ManualClock {
+        fn now(&self) -> Instant {
+            self.start + *self.elapsed.lock().unwrap()
+        }
+
+        fn system_now(&self) -> SystemTime {
+            self.system_start + *self.elapsed.lock().unwrap() + *self.asleep.lock().unwrap()
+        }
+
+        fn recv_until(&self, rx: &Receiver<TimerCommand>, deadline: Option<Instant>) -> Result<TimerCommand, RecvTimeoutError> {
+            // Poll in short real-time slices, checking the virtual deadline in between
+            loop {
+                match rx.recv_timeout(Duration::from_millis(1)) {
+                    Err(RecvTimeoutError::Timeout) => {
+                        if deadline.is_some_and(|d| self.now() >= d) {
+                            return Err(RecvTimeoutError::Timeout);
+                        }
+                    }
+                    result => return result,
+                }
+            }
+        }
+    }
+}
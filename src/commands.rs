@@ -0,0 +1,640 @@
+/*
+Commands are called from main.rs when the program is given arguments.
+They allow the timer to be scripted or launched with one-off settings, without going through the menus.
+Like the CLI, they only pass instructions on to the relevant service.
+*/
+use std::fs::File;
+use std::io::{self, BufWriter, IsTerminal, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use chrono::{Days, NaiveDate};
+use clap::{Args, Parser, Subcommand};
+use serde_json::Value;
+
+use crate::PomodoroApp;
+use crate::cli;
+use crate::config_format::ConfigFormat;
+use crate::config_layers::{self, Origin, Override};
+use crate::config_manager::ConfigError;
+use crate::daemon::{self, Request, StartOverrides, TimerStatus};
+use crate::export::{self, ExportFormat};
+use crate::history::{History, HistoryFilter};
+use crate::report;
+use crate::status_bar::{self, StatusFormat};
+use crate::tasks::{self, Task, TaskList, TaskStatus};
+use crate::timer::{TimerSession, TimerState};
+use crate::utils::{get_display_time, get_input, parse_date, parse_duration, parse_local_date};
+
+#[derive(Parser)]
+#[command(name = "rustypomodoro", version, about = "A pomodoro timer, modified for eye strain management")]
+#[command(after_help = "Run without a command to open the interactive menu.")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Use this config file instead of the one in the config directory
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+    /// Override a setting for this run only, e.g. --set work=25m. Can be given more than once
+    #[arg(long = "set", global = true, value_name = "KEY=VALUE", value_parser = config_layers::cli_override)]
+    pub set: Vec<Override>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Start the timer, optionally overriding saved settings for this run only
+    Start(StartArgs),
+    /// Read or change the saved settings
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommand,
+    },
+    /// Manage the named settings profiles
+    Profile {
+        #[command(subcommand)]
+        action: ProfileCommand,
+    },
+    /// Manage the tasks that work sessions are recorded against
+    Task {
+        #[command(subcommand)]
+        action: TaskCommand,
+    },
+    /// Show the sessions recorded so far
+    History(HistoryArgs),
+    /// Write the recorded sessions out as CSV, JSON lines or iCalendar
+    Export(ExportArgs),
+    /// Summarise focused time, breaks and eye relief over a range of days
+    Report(ReportArgs),
+    /// Show the state of the timer running in the daemon
+    Status(StatusArgs),
+    /// Run the background daemon that owns the timer
+    Daemon {
+        /// Start the daemon in the background and return
+        #[arg(long)]
+        detach: bool,
+    },
+    /// Pause the daemon's timer
+    Pause,
+    /// Resume the daemon's paused timer
+    Resume,
+    /// Move the daemon's timer on to the next session
+    Next,
+    /// End the daemon's current session early, or pass over the one about to start
+    Skip,
+    /// Start the daemon's current session over
+    Restart,
+    /// Add the snooze length to the daemon's current session
+    Snooze,
+    /// Stop the daemon's timer. A session counting down or paused is recorded as aborted, so this asks first
+    Stop {
+        /// Stop without asking, even if a session is under way
+        #[arg(long, short)]
+        yes: bool,
+    },
+    /// Stop the background daemon
+    Shutdown,
+}
+
+#[derive(Args)]
+pub struct StartArgs {
+    /// Length of a work session, e.g. 25m
+    #[arg(long, value_parser = parse_session_length)]
+    pub work: Option<u32>,
+    /// Length of an eye relief session, e.g. 20s
+    #[arg(long, value_parser = parse_session_length)]
+    pub relief: Option<u32>,
+    /// Length of the long break, e.g. 5m
+    #[arg(long = "break", value_parser = parse_session_length)]
+    pub break_time: Option<u32>,
+    /// Number of work-relief sessions before the long break
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub cycles: Option<u32>,
+    /// Run the timer in the background daemon instead of this terminal, starting the daemon if needed
+    #[arg(long)]
+    pub daemon: bool,
+    /// ID or name of the task to work on. A name that doesn't match a task creates a new one
+    #[arg(long)]
+    pub task: Option<String>,
+    /// Use this profile's settings instead of the active profile's
+    #[arg(long)]
+    pub profile: Option<String>,
+}
+
+// A session has to last at least a second, or the timer would do nothing but move on
+fn parse_session_length(s: &str) -> Result<u32, String> {
+    match parse_duration(s)? {
+        0 => Err("A session can't last 0 seconds".to_string()),
+        seconds => Ok(seconds),
+    }
+}
+
+impl StartArgs {
+    fn overrides(&self) -> StartOverrides {
+        StartOverrides {
+            work_seconds: self.work,
+            relief_seconds: self.relief,
+            break_seconds: self.break_time,
+            work_relief_cycles: self.cycles,
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct HistoryArgs {
+    /// Only show this many of the most recent sessions
+    #[arg(long, default_value_t = 20)]
+    pub limit: usize,
+    #[command(flatten)]
+    pub filter: FilterArgs,
+}
+
+#[derive(Args)]
+pub struct ExportArgs {
+    /// Output format
+    #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+    pub format: ExportFormat,
+    /// Write to this file instead of standard output
+    #[arg(long, short)]
+    pub output: Option<PathBuf>,
+    #[command(flatten)]
+    pub filter: FilterArgs,
+}
+
+#[derive(Args)]
+pub struct FilterArgs {
+    /// Only include sessions of this type (working, resting or break)
+    #[arg(long, value_parser = parse_session)]
+    pub session: Option<TimerSession>,
+    /// Only include sessions started on or after this date (YYYY-MM-DD)
+    #[arg(long, value_parser = parse_local_date)]
+    pub since: Option<i64>,
+    /// Only include sessions started before this date (YYYY-MM-DD)
+    #[arg(long, value_parser = parse_local_date)]
+    pub until: Option<i64>,
+}
+
+impl FilterArgs {
+    fn filter(&self) -> HistoryFilter {
+        HistoryFilter { since: self.since, until: self.until, session: self.session, ..Default::default() }
+    }
+}
+
+fn parse_session(s: &str) -> Result<TimerSession, String> {
+    match s.to_lowercase().as_str() {
+        "working" | "work" => Ok(TimerSession::Working),
+        "resting" | "relief" => Ok(TimerSession::Resting),
+        "break" => Ok(TimerSession::Break),
+        _ => Err(format!("Unknown session type '{s}' (use working, resting or break)")),
+    }
+}
+
+#[derive(Args)]
+pub struct ReportArgs {
+    /// First day to include (YYYY-MM-DD). Defaults to a week before --to
+    #[arg(long, value_parser = parse_date)]
+    pub from: Option<NaiveDate>,
+    /// Last day to include (YYYY-MM-DD). Defaults to today
+    #[arg(long, value_parser = parse_date)]
+    pub to: Option<NaiveDate>,
+    /// Only report on today
+    #[arg(long, conflicts_with_all = ["from", "to"])]
+    pub today: bool,
+}
+
+impl ReportArgs {
+    fn range(&self) -> Result<(NaiveDate, NaiveDate), String> {
+        let (week_start, today) = report::last_days(7);
+        if self.today {
+            return Ok((today, today));
+        }
+        let to = self.to.unwrap_or(today);
+        let from = match self.from {
+            Some(from) => from,
+            None if self.to.is_none() => week_start,
+            None => to.checked_sub_days(Days::new(6)).unwrap_or(to),
+        };
+        if from > to {
+            return Err(format!("--from {from} is after --to {to}"));
+        }
+        Ok((from, to))
+    }
+}
+
+#[derive(Args)]
+pub struct StatusArgs {
+    /// Keep printing the status every time it changes
+    #[arg(long)]
+    pub follow: bool,
+    /// Output format, for status bars such as waybar, i3bar/i3blocks, polybar or tmux
+    #[arg(long, value_enum, default_value_t = StatusFormat::Plain)]
+    pub format: StatusFormat,
+    /// Text to show, with placeholders {state} {session} {segment} {remaining} {cycle} {total_cycles} {percentage}
+    #[arg(long, default_value = status_bar::DEFAULT_TEMPLATE)]
+    pub template: String,
+}
+
+#[derive(Subcommand)]
+pub enum ProfileCommand {
+    /// List the profiles, marking the active one
+    List,
+    /// Create a profile with the default settings
+    Create { name: String },
+    /// Create a profile with a copy of another profile's settings
+    Clone { from: String, to: String },
+    /// Give a profile a new name
+    Rename { from: String, to: String },
+    /// Delete a profile other than the active one
+    Delete { name: String },
+    /// Make a profile the active one
+    Switch { name: String },
+}
+
+#[derive(Subcommand)]
+pub enum TaskCommand {
+    /// Create a new task
+    Add {
+        name: String,
+        /// Number of pomodoros (work sessions) the task should take
+        #[arg(long)]
+        estimate: Option<u32>,
+    },
+    /// List open tasks, with the pomodoros done against their estimate
+    List {
+        /// Include completed and archived tasks
+        #[arg(long)]
+        all: bool,
+    },
+    /// Give a task a new name
+    Rename { task: String, name: String },
+    /// Mark a task as completed
+    Done { task: String },
+    /// Hide a task from the task lists
+    Archive { task: String },
+    /// Set how many pomodoros a task should take, or 0 to clear the estimate
+    Estimate { task: String, pomodoros: u32 },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommand {
+    /// Print one setting, or all of them if no key is given
+    Get { key: Option<String> },
+    /// Print every setting in effect, with overrides from the environment or --set applied
+    Show {
+        /// Also show where each value came from: default, file, environment or command line
+        #[arg(long)]
+        origin: bool,
+    },
+    /// Change and save one setting. Durations accept values such as 25m or 1h30m
+    Set { key: String, value: String },
+    /// Print the location of the config file
+    Path,
+    /// Rewrite the config file in another format, replacing the current one
+    Convert { format: ConfigFormat },
+}
+
+pub fn run(app: &mut PomodoroApp, command: Command) -> ExitCode {
+    let result = match command {
+        Command::Start(args) => start(app, args),
+        Command::Config { action: ConfigCommand::Get { key } } => config_get(app, key.as_deref()),
+        Command::Config { action: ConfigCommand::Show { origin } } => config_show(app, origin),
+        Command::Config { action: ConfigCommand::Set { key, value } } => config_set(app, &key, &value),
+        Command::Config { action: ConfigCommand::Path } => config_path(app),
+        Command::Config { action: ConfigCommand::Convert { format } } => config_convert(app, format),
+        Command::Profile { action } => profile(app, action),
+        Command::Task { action } => task(action),
+        Command::History(args) => history(&args),
+        Command::Export(args) => export(&args),
+        Command::Report(args) => args.range().and_then(|(from, to)| report::load(from, to))
+            .map(|report| print!("{}", report::render(&report))),
+        Command::Status(args) => status(&args),
+        Command::Daemon { detach } => run_daemon(app, detach),
+        Command::Pause => send_to_daemon(Request::Pause),
+        Command::Resume => send_to_daemon(Request::Resume),
+        Command::Next => send_to_daemon(Request::Next),
+        Command::Skip => send_to_daemon(Request::Skip),
+        Command::Restart => send_to_daemon(Request::Restart),
+        Command::Snooze => send_to_daemon(Request::Snooze),
+        Command::Stop { yes } => stop(yes),
+        Command::Shutdown => daemon::get_socket_path()
+            .and_then(|path| daemon::request(&path, Request::Shutdown))
+            .map(|_| ()),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn start(app: &mut PomodoroApp, args: StartArgs) -> Result<(), String> {
+    let task = match &args.task {
+        Some(task) => Some(resolve_or_create_task(task)?),
+        None => None,
+    };
+
+    if args.daemon {
+        let path = daemon::get_socket_path()?;
+        if daemon::request(&path, Request::Status).is_err() {
+            daemon::spawn_detached(&path, &app.config().layer_args())?;
+        }
+        let status = daemon::request(&path, Request::Start { overrides: args.overrides(), task, profile: args.profile })?;
+        println!("{}", format_status(&status));
+        return Ok(());
+    }
+
+    let mut settings = match &args.profile {
+        Some(name) => app.config().profile_settings(name).ok_or_else(|| format!("No profile named '{name}'"))?,
+        None => app.get_settings().clone(),
+    };
+    args.overrides().apply(&mut settings);
+    settings.validate().map_err(|e| e.to_string())?;
+
+    // A session cut short earlier is offered back first. Without a terminal to ask in, it goes into history
+    // rather than being lost
+    if let Some(snapshot) = app.interrupted_session() {
+        if io::stdin().is_terminal() {
+            if cli::cli_recover_session(app, snapshot) {
+                return Ok(());
+            }
+        } else {
+            app.log_interrupted(&snapshot).map_err(|e| format!("Could not log the interrupted session: {e}"))?;
+            eprintln!("Logged the interrupted session to history: {}", snapshot.describe());
+        }
+    }
+    app.override_settings(settings);
+
+    cli::cli_run_timer(app, task).map_err(|e| format!("Could not run the timer: {e}"))
+}
+
+fn run_daemon(app: &mut PomodoroApp, detach: bool) -> Result<(), String> {
+    let path = daemon::get_socket_path()?;
+    if detach {
+        return daemon::spawn_detached(&path, &app.config().layer_args());
+    }
+    daemon::serve(app, &path).map_err(|e| format!("Could not run the daemon: {e}"))
+}
+
+fn send_to_daemon(request: Request) -> Result<(), String> {
+    let status = daemon::request(&daemon::get_socket_path()?, request)?;
+    println!("{}", format_status(&status));
+    Ok(())
+}
+
+// Stopping cuts a session that is counting down short, so that needs confirming. Scripts confirm with --yes
+fn stop(yes: bool) -> Result<(), String> {
+    let path = daemon::get_socket_path()?;
+    if !yes && daemon::request(&path, Request::Status)?.state.is_session_under_way() {
+        if !io::stdin().is_terminal() {
+            return Err("A session is under way. Stopping records it as aborted, pass --yes to stop anyway".to_string());
+        }
+        let answer = get_input("A session is under way. Stop it and record it as aborted? [y/N]");
+        if !answer.eq_ignore_ascii_case("y") {
+            return Err("Not stopped".to_string());
+        }
+    }
+    send_to_daemon(Request::Stop)
+}
+
+fn status(args: &StatusArgs) -> Result<(), String> {
+    let path = daemon::get_socket_path()?;
+    let print = |status: &TimerStatus| println!("{}", status_bar::render(status, args.format, &args.template));
+    if args.format == StatusFormat::I3bar {
+        println!("{}", status_bar::I3BAR_HEADER);
+    }
+
+    // Bars get an idle line rather than an error, so they keep showing something sensible
+    let for_bar = args.format != StatusFormat::Plain;
+    let result = if args.follow {
+        // The subscription sends the current status first, so there is nothing to fetch beforehand
+        daemon::subscribe(&path, |status| {
+            print(status);
+            true
+        }).map(|()| None)
+    } else {
+        daemon::request(&path, Request::Status).map(Some)
+    };
+    let status = match result {
+        Ok(Some(status)) => status,
+        Ok(None) => return Ok(()),
+        Err(_) if for_bar => TimerStatus::idle(0),
+        Err(e) => return Err(e),
+    };
+
+    print(&status);
+    if status.state == TimerState::Idle && !for_bar {
+        return Err("No timer is running.".to_string());
+    }
+    Ok(())
+}
+
+fn format_status(status: &TimerStatus) -> String {
+    status_bar::render(status, StatusFormat::Plain, status_bar::DEFAULT_TEMPLATE)
+}
+
+fn profile(app: &mut PomodoroApp, action: ProfileCommand) -> Result<(), String> {
+    let config = app.config_mut();
+    match action {
+        ProfileCommand::List => {
+            for name in config.profile_names() {
+                let marker = if name == config.active_profile() { "*" } else { " " };
+                println!("{marker} {name}");
+            }
+            Ok(())
+        }
+        ProfileCommand::Create { name } => config.create_profile(&name),
+        ProfileCommand::Clone { from, to } => config.clone_profile(&from, &to),
+        ProfileCommand::Rename { from, to } => config.rename_profile(&from, &to),
+        ProfileCommand::Delete { name } => config.delete_profile(&name),
+        ProfileCommand::Switch { name } => config.switch_profile(&name),
+    }.map_err(|e| e.to_string())
+}
+
+fn task(action: TaskCommand) -> Result<(), String> {
+    let mut list = TaskList::open_default()?;
+    match action {
+        TaskCommand::Add { name, estimate } => {
+            let id = list.create(&name, estimate.filter(|e| *e > 0))?;
+            println!("Created task {id}");
+        }
+        TaskCommand::List { all } => {
+            let tasks: Vec<&Task> = list.tasks().iter()
+                .filter(|task| all || task.status == TaskStatus::Open)
+                .collect();
+            if tasks.is_empty() {
+                println!("No tasks yet. Add one with: rustypomodoro task add NAME");
+            } else {
+                print!("{}", tasks::render_table(tasks.into_iter(), &tasks::recorded_actuals()));
+            }
+            return Ok(());
+        }
+        TaskCommand::Rename { task, name } => list.rename(find_task(&list, &task)?, &name)?,
+        TaskCommand::Done { task } => list.set_status(find_task(&list, &task)?, TaskStatus::Completed)?,
+        TaskCommand::Archive { task } => list.set_status(find_task(&list, &task)?, TaskStatus::Archived)?,
+        TaskCommand::Estimate { task, pomodoros } => {
+            list.set_estimate(find_task(&list, &task)?, Some(pomodoros).filter(|p| *p > 0))?
+        }
+    }
+    list.save()
+}
+
+fn find_task(list: &TaskList, id_or_name: &str) -> Result<u32, String> {
+    list.find(id_or_name).map(|task| task.id).ok_or_else(|| format!("No task matches '{id_or_name}'"))
+}
+
+fn resolve_or_create_task(id_or_name: &str) -> Result<u32, String> {
+    let mut list = TaskList::open_default()?;
+    if let Some(task) = list.find(id_or_name) {
+        return Ok(task.id);
+    }
+    if id_or_name.trim().parse::<u32>().is_ok() {
+        return Err(format!("No task with ID {id_or_name}"));
+    }
+    let id = list.create(id_or_name, None)?;
+    list.save()?;
+    Ok(id)
+}
+
+fn history(args: &HistoryArgs) -> Result<(), String> {
+    let history = History::open_default()?;
+    let records = history.query(&args.filter.filter())
+        .map_err(|e| format!("Could not read {}: {e}", history.path().display()))?;
+    if records.is_empty() {
+        println!("No sessions recorded yet.");
+        return Ok(());
+    }
+
+    println!("{:<17} {:<8} {:<10} {:>8} {:>8} {:>8} {:>5}", "Started", "Session", "Outcome", "Planned", "Actual", "Paused", "Cycle");
+    for record in &records[records.len().saturating_sub(args.limit)..] {
+        println!("{:<17} {:<8} {:<10} {:>8} {:>8} {:>8} {:>5}",
+            record.started_local().format("%Y-%m-%d %H:%M"),
+            record.session.as_str(),
+            record.outcome.as_str(),
+            get_display_time(record.planned_seconds),
+            get_display_time(record.actual_seconds),
+            get_display_time(record.paused_seconds),
+            record.cycle + 1,
+        );
+    }
+    Ok(())
+}
+
+fn export(args: &ExportArgs) -> Result<(), String> {
+    let history = History::open_default()?;
+    let records = history.query(&args.filter.filter())
+        .map_err(|e| format!("Could not read {}: {e}", history.path().display()))?;
+    // Without the task list, sessions are exported with their task IDs
+    let tasks = TaskList::open_default().map(|list| list.tasks().to_vec()).unwrap_or_default();
+    let session = args.filter.session;
+
+    match &args.output {
+        Some(path) => {
+            let file = File::create(path).map_err(|e| format!("Could not create {}: {e}", path.display()))?;
+            let mut out = BufWriter::new(file);
+            export::export(&records, args.format, session, &tasks, &mut out)
+                .and_then(|_| out.flush())
+                .map_err(|e| format!("Could not write {}: {e}", path.display()))
+        }
+        None => export::export(&records, args.format, session, &tasks, &mut io::stdout().lock())
+            .map_err(|e| format!("Could not write the export: {e}")),
+    }
+}
+
+fn config_get(app: &PomodoroApp, key: Option<&str>) -> Result<(), String> {
+    let map = config_layers::settings_map(app.get_settings());
+    match key {
+        Some(key) => {
+            let value = map.get(key).ok_or_else(|| unknown_key(key, &map))?;
+            match value {
+                Value::String(s) => println!("{s}"),
+                other => println!("{other}"),
+            }
+        }
+        None => println!("{}", serde_json::to_string_pretty(&map).map_err(|e| e.to_string())?),
+    }
+    Ok(())
+}
+
+fn config_show(app: &PomodoroApp, origin: bool) -> Result<(), String> {
+    let origins = app.config().origins();
+    let key_width = origins.iter().map(|(key, _, _)| key.len()).max().unwrap_or(0);
+    let values: Vec<String> = origins.iter().map(|(_, value, _)| match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }).collect();
+    let value_width = values.iter().map(|value| value.chars().count()).max().unwrap_or(0);
+
+    for ((key, _, from), value) in origins.iter().zip(values) {
+        if origin {
+            println!("{key:<key_width$}  {value:<value_width$}  {from}");
+        } else {
+            println!("{key:<key_width$}  {value}");
+        }
+    }
+    Ok(())
+}
+
+fn config_set(app: &mut PomodoroApp, key: &str, value: &str) -> Result<(), String> {
+    // The saved settings, kept even when they don't validate, so setting one key can't lose the others
+    let current = app.config().settings_to_edit();
+    let map = config_layers::settings_map(&current);
+    let key = config_layers::setting_key(key).ok_or_else(|| unknown_key(key, &map))?;
+    let new_value = config_layers::parse_value(&key, value)?;
+    let settings = config_layers::with_value(&current, &key, new_value)?;
+    app.save_config(settings).map_err(|e| e.to_string())?;
+
+    // Saved, but not what this run will use
+    if let Some((_, _, origin @ (Origin::Env(_) | Origin::Cli(_)))) = app.config().origins().into_iter().find(|(k, _, _)| *k == key) {
+        eprintln!("Note: {key} is overridden by {origin}");
+    }
+    Ok(())
+}
+
+// The file this run uses, so --config and the format the config was found in are taken into account
+fn config_path(app: &PomodoroApp) -> Result<(), String> {
+    let path = app.config().path().ok_or_else(|| ConfigError::DirUnresolved.to_string())?;
+    println!("{}", path.display());
+    Ok(())
+}
+
+fn config_convert(app: &mut PomodoroApp, format: ConfigFormat) -> Result<(), String> {
+    let path = app.config_mut().convert(format).map_err(|e| e.to_string())?;
+    println!("Config converted to {}: {}", format.as_str(), path.display());
+    Ok(())
+}
+
+fn unknown_key(key: &str, map: &serde_json::Map<String, Value>) -> String {
+    let keys: Vec<&str> = map.keys().map(String::as_str).collect();
+    format!("Unknown setting '{key}'. Available settings: {}", keys.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_manager::ConfigManager;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn config_set_on_an_invalid_file_keeps_the_other_saved_keys() {
+        let dir = std::env::temp_dir().join(format!("rustypomodoro-commands-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.json");
+        let fixture = fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/config/v2.json")).unwrap();
+        let invalid = fixture.replace("\"work_seconds\": 1500", "\"work_seconds\": 1800")
+            .replace("\"work_relief_cycles\": 3", "\"work_relief_cycles\": 0");
+        fs::write(&path, invalid).unwrap();
+
+        let mut app = PomodoroApp::new();
+        assert!(matches!(app.config_mut().build(Some(path.clone()), Vec::new()), Err(ConfigError::Invalid(_))));
+        config_set(&mut app, "work_relief_cycles", "4").unwrap();
+
+        let mut saved = ConfigManager::new();
+        saved.build(Some(path), Vec::new()).unwrap();
+        assert_eq!((saved.get_settings().work_seconds, saved.get_settings().work_relief_cycles), (1800, 4));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,94 @@
+/*
+The config file can be written in TOML, YAML or JSON, told apart by its extension. Each is read into the
+same JSON value first, so versions and migrations work the same whichever format the file is in.
+Saving rewrites the whole file, so comments in a TOML or YAML config don't survive a save.
+*/
+use std::path::Path;
+
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::config_manager::ConfigError;
+
+#[derive(Copy, Clone, Debug, PartialEq, ValueEnum)]
+pub enum ConfigFormat {
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl ConfigFormat {
+    // When the config directory holds more than one config file, the first found in this order is used
+    pub const PRECEDENCE: [ConfigFormat; 3] = [Self::Toml, Self::Yaml, Self::Json];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Toml => "TOML",
+            Self::Yaml => "YAML",
+            Self::Json => "JSON",
+        }
+    }
+
+    // Name a config in this format is written under
+    pub fn file_name(&self) -> &'static str {
+        self.file_names()[0]
+    }
+
+    // Every name a config in this format is looked for under, in order
+    pub fn file_names(&self) -> &'static [&'static str] {
+        match self {
+            Self::Toml => &["config.toml"],
+            Self::Yaml => &["config.yaml", "config.yml"],
+            Self::Json => &["config.json"],
+        }
+    }
+
+    // Anything without a TOML or YAML extension is taken to be JSON
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Self::Toml,
+            Some("yaml" | "yml") => Self::Yaml,
+            _ => Self::Json,
+        }
+    }
+
+    pub fn parse(&self, path: &Path, contents: &str) -> Result<Value, ConfigError> {
+        match self {
+            Self::Toml => toml::from_str(contents).map_err(|e| {
+                let (line, column) = e.span().map_or((1, 1), |span| line_and_column(contents, span.start));
+                parse_error(path, line, column, e.message())
+            }),
+            Self::Yaml => serde_yaml_ng::from_str(contents).map_err(|e| {
+                let (line, column) = e.location().map_or((1, 1), |at| (at.line(), at.column()));
+                parse_error(path, line, column, &e.to_string())
+            }),
+            Self::Json => serde_json::from_str(contents).map_err(|e| parse_error(path, e.line(), e.column(), &e.to_string())),
+        }
+    }
+
+    pub fn serialize(&self, value: &impl Serialize) -> Result<String, ConfigError> {
+        match self {
+            Self::Toml => toml::to_string_pretty(value).map_err(|e| e.to_string()),
+            // Through a JSON value, so enums come out as maps rather than YAML tags we couldn't read back
+            Self::Yaml => serde_json::to_value(value)
+                .map_err(|e| e.to_string())
+                .and_then(|value| serde_yaml_ng::to_string(&value).map_err(|e| e.to_string())),
+            Self::Json => serde_json::to_string_pretty(value).map_err(|e| e.to_string()),
+        }
+        .map_err(|message| ConfigError::Serialize { format: *self, message })
+    }
+}
+
+fn parse_error(path: &Path, line: usize, column: usize, message: &str) -> ConfigError {
+    // serde_json and serde_yaml end their messages with the position, which we show our own way
+    let message = message.split(" at line ").next().unwrap_or_default().trim().to_string();
+    ConfigError::Parse { path: path.to_path_buf(), line, column, message }
+}
+
+// Position of a byte offset, counting from 1 as editors do
+fn line_and_column(contents: &str, offset: usize) -> (usize, usize) {
+    let before = &contents[..offset.min(contents.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+}
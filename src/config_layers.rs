@@ -0,0 +1,166 @@
+/*
+Settings can be overridden for a single run without touching the config file, from environment variables
+(RUSTY_POMODORO_WORK=25m) or the command line (--set work=25m). Each layer goes over the one before it:
+the defaults, then the config file, then the environment, then the command line.
+*/
+use std::fmt;
+use std::path::PathBuf;
+
+use serde_json::{Map, Value};
+
+use crate::config_manager::Settings;
+use crate::utils::parse_duration;
+
+pub const ENV_PREFIX: &str = "RUSTY_POMODORO_";
+
+// Short names for the settings changed most often
+const ALIASES: [(&str, &str); 5] = [
+    ("work", "work_seconds"),
+    ("relief", "relief_seconds"),
+    ("break", "break_seconds"),
+    ("cycles", "work_relief_cycles"),
+    ("snooze", "snooze_seconds"),
+];
+
+// Where an effective setting's value came from
+#[derive(Clone, Debug, PartialEq)]
+pub enum Origin {
+    Default,
+    File(PathBuf),
+    // Name of the variable
+    Env(String),
+    // The --set argument as it was given
+    Cli(String),
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Default => write!(f, "default"),
+            Self::File(path) => write!(f, "file {}", path.display()),
+            Self::Env(name) => write!(f, "environment {name}"),
+            Self::Cli(arg) => write!(f, "command line --set {arg}"),
+        }
+    }
+}
+
+// A setting's value for this run, from a layer above the config file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Override {
+    pub key: String,
+    pub value: Value,
+    pub origin: Origin,
+}
+
+impl Override {
+    // `name` is the setting's key or one of its short names
+    fn parse(name: &str, text: &str, origin: Origin) -> Result<Self, String> {
+        let key = setting_key(name).ok_or_else(|| format!("Unknown setting '{name}'"))?;
+        let value = parse_value(&key, text)?;
+        // Catch a value the setting can't take now, rather than when it's applied. Rules between settings
+        // are checked once every layer is in place
+        if let Some(violation) = with_value(&Settings::default(), &key, value.clone())?.limit_violation(&key) {
+            return Err(violation);
+        }
+        Ok(Self { key, value, origin })
+    }
+}
+
+// Parse a --set argument, given as KEY=VALUE
+pub fn cli_override(arg: &str) -> Result<Override, String> {
+    let (name, text) = arg.split_once('=').ok_or("Expected KEY=VALUE")?;
+    Override::parse(name.trim(), text, Origin::Cli(arg.to_string()))
+}
+
+// Overrides from the RUSTY_POMODORO_* variables among `vars`, along with why any of them couldn't be used
+pub fn env_overrides(vars: impl Iterator<Item = (String, String)>) -> (Vec<Override>, Vec<String>) {
+    let mut vars: Vec<(String, String)> = vars.filter(|(name, _)| name.starts_with(ENV_PREFIX)).collect();
+    // Sorted so that a setting given under both its key and a short name always resolves the same way
+    vars.sort();
+    let mut overrides = Vec::new();
+    let mut errors = Vec::new();
+    for (name, text) in vars {
+        let setting = name[ENV_PREFIX.len()..].to_lowercase();
+        match Override::parse(&setting, &text, Origin::Env(name.clone())) {
+            Ok(value) => overrides.push(value),
+            Err(e) => errors.push(format!("{name}: {e}")),
+        }
+    }
+    (overrides, errors)
+}
+
+// The settings with every override applied over them, later ones winning
+pub fn layered(settings: &Settings, overrides: &[Override]) -> Settings {
+    if overrides.is_empty() {
+        return settings.clone();
+    }
+    let mut map = settings_map(settings);
+    for value in overrides {
+        map.insert(value.key.clone(), value.value.clone());
+    }
+    // Each override was checked when it was parsed, so this only fails if the settings themselves can't be read
+    serde_json::from_value(Value::Object(map)).unwrap_or_else(|_| settings.clone())
+}
+
+// The settings by key, as they appear in the config file
+pub fn settings_map(settings: &Settings) -> Map<String, Value> {
+    match serde_json::to_value(settings) {
+        Ok(Value::Object(map)) => map,
+        _ => Map::new(),
+    }
+}
+
+// The settings with one of them changed
+pub fn with_value(settings: &Settings, key: &str, value: Value) -> Result<Settings, String> {
+    let mut map = settings_map(settings);
+    map.insert(key.to_string(), value);
+    serde_json::from_value(Value::Object(map)).map_err(|e| format!("Invalid value for {key}: {e}"))
+}
+
+// The setting a key or short name refers to
+pub fn setting_key(name: &str) -> Option<String> {
+    let name = ALIASES.iter().find(|(alias, _)| *alias == name).map_or(name, |(_, key)| *key);
+    settings_map(&Settings::default()).contains_key(name).then(|| name.to_string())
+}
+
+// A setting's value from text, as typed in a command or an environment variable
+pub fn parse_value(key: &str, text: &str) -> Result<Value, String> {
+    if key.ends_with("_seconds") {
+        return parse_duration(text).map(Value::from);
+    }
+    // Anything that isn't valid JSON (e.g. tone) is taken as a plain string
+    Ok(serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.to_string())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alert::AlertSound;
+
+    #[test]
+    fn later_layers_win() {
+        let vars = [
+            ("RUSTY_POMODORO_WORK", "25m"),
+            ("RUSTY_POMODORO_CYCLES", "4"),
+            ("RUSTY_POMODORO_ALERT_SOUND", "tone"),
+            ("RUSTY_POMODORO_BREAK", "soon"),
+            ("RUSTY_POMODORO_NAPS", "1"),
+            ("HOME", "/home/me"),
+        ].map(|(name, value)| (name.to_string(), value.to_string()));
+        let (mut overrides, errors) = env_overrides(vars.into_iter());
+        assert_eq!(overrides.len(), 3);
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].starts_with("RUSTY_POMODORO_BREAK: "));
+        assert_eq!(errors[1], "RUSTY_POMODORO_NAPS: Unknown setting 'naps'");
+
+        overrides.push(cli_override("work_seconds=30m").unwrap());
+        assert!(cli_override("work").is_err());
+        assert!(cli_override("cycles=many").is_err());
+        assert_eq!(cli_override("cycles=0").unwrap_err(), "work_relief_cycles must be between 1 and 24 (is 0)");
+
+        let settings = layered(&Settings::default(), &overrides);
+        assert_eq!((settings.work_seconds, settings.work_relief_cycles), (30 * 60, 4));
+        assert_eq!(settings.alert_sound, AlertSound::Tone);
+        assert_eq!(overrides[3].origin.to_string(), "command line --set work_seconds=30m");
+    }
+}
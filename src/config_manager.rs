@@ -1,110 +1,1262 @@
+use crate::alert::{AlertOutput, AlertSound};
+use crate::utils::{format_duration, get_display_time};
+use crate::config_format::ConfigFormat;
+use crate::config_layers::{self, Origin, Override};
+use crate::timer::{SuspendPolicy, TimerSession};
 use directories::ProjectDirs;
 use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, BTreeSet};
 use std::error::Error;
 use std::fmt;
-use std::fs::{create_dir_all, File};
-use std::io::{BufReader, Write};
-use std::path::PathBuf;
+use serde_json::{json, Value};
+use std::fs::{self, create_dir_all, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-#[derive(Default, Serialize, Deserialize, Clone)]
+// Fields missing from a config file take their value from Settings::default(), so new settings can be
+// added without touching the files already out there
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
 pub struct Settings {
     pub work_seconds: u32,
     pub relief_seconds: u32,
     pub break_seconds: u32,
     pub work_relief_cycles: u32,
+    // Start the next session without waiting for input, by the type of session coming up
+    pub auto_start_work: bool,
+    pub auto_start_relief: bool,
+    pub auto_start_break: bool,
+    pub auto_advance_grace_seconds: u32,
+    pub alert_sound: AlertSound,
+    pub alert_output: AlertOutput,
+    // Replay the alert this often while waiting for the next session, 0 to play it only once
+    pub alert_repeat_seconds: u32,
+    // Time a snooze adds to the session, and how many snoozes each session allows (0 to turn snoozing off)
+    pub snooze_seconds: u32,
+    pub max_snoozes: u32,
+    // Announce session changes as desktop notifications (needs the notifications feature)
+    pub desktop_notifications: bool,
+    // What to make of the machine sleeping mid-session, for sleeps at least the threshold long
+    pub suspend_policy: SuspendPolicy,
+    pub suspend_threshold_seconds: u32,
+    // Custom order of sessions to cycle through. Left empty, the work, relief and break settings above
+    // make up the sequence
+    pub sequence: Vec<Segment>,
 }
 
-impl fmt::Display for Settings {
+impl Default for Settings {
+    // The eye strain setup this timer was made for
+    fn default() -> Self {
+        Self {
+            work_seconds: 20*60,
+            relief_seconds: 20,
+            break_seconds: 5*60,
+            work_relief_cycles: 2,
+            auto_start_work: false,
+            auto_start_relief: false,
+            auto_start_break: false,
+            auto_advance_grace_seconds: 0,
+            alert_sound: AlertSound::default(),
+            alert_output: AlertOutput::default(),
+            alert_repeat_seconds: 0,
+            snooze_seconds: 5*60,
+            max_snoozes: 3,
+            desktop_notifications: false,
+            suspend_policy: SuspendPolicy::default(),
+            suspend_threshold_seconds: 60,
+            sequence: Vec::new(),
+        }
+    }
+}
+
+// One step of a session sequence
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Segment {
+    pub name: String,
+    pub session: TimerSession,
+    pub seconds: u32,
+    // Start this segment without waiting for input once the one before it ends
+    #[serde(default)]
+    pub auto_advance: bool,
+}
+
+impl Segment {
+    pub fn new(name: &str, session: TimerSession, seconds: u32, auto_advance: bool) -> Self {
+        Self { name: name.to_string(), session, seconds, auto_advance }
+    }
+}
+
+impl fmt::Display for Segment {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Work for {} minutes, Rest for {} seconds, for {} cycles, then break for {} minutes", 
-            self.work_seconds/60, self.relief_seconds, self.work_relief_cycles, self.break_seconds/60
+        write!(f, "{} ({}, {}{})", self.name, self.session.as_str(), get_display_time(self.seconds),
+            if self.auto_advance {", auto-start"} else {""}
         )
     }
 }
 
+impl Settings {
+    // The sequence the timer walks through, repeating from the start once it reaches the end
+    pub fn sequence(&self) -> Vec<Segment> {
+        if !self.sequence.is_empty() {
+            return self.sequence.clone();
+        }
+        // The default preset: work and eye relief in turn, with the last relief replaced by the long break
+        let mut sequence = Vec::new();
+        for cycle in 1..=self.work_relief_cycles.max(1) {
+            sequence.push(Segment::new("Work", TimerSession::Working, self.work_seconds, self.auto_start_work));
+            if cycle < self.work_relief_cycles {
+                sequence.push(Segment::new("Eye relief", TimerSession::Resting, self.relief_seconds, self.auto_start_relief));
+            } else {
+                sequence.push(Segment::new("Break", TimerSession::Break, self.break_seconds, self.auto_start_break));
+            }
+        }
+        sequence
+    }
+
+    // Work sessions in one pass through the sequence
+    pub fn total_cycles(&self) -> u32 {
+        let work = self.sequence().iter().filter(|segment| segment.session == TimerSession::Working).count();
+        (work as u32).max(1)
+    }
+
+    // Every way in which these settings are out of bounds or don't fit together
+    pub fn violations(&self) -> Vec<String> {
+        let mut violations: Vec<String> = LIMITS.iter().filter_map(|limit| self.limit_violation(limit.key)).collect();
+        if self.sequence.is_empty() && self.relief_seconds >= self.work_seconds {
+            violations.push(format!("relief_seconds ({}) must be shorter than work_seconds ({})",
+                format_duration(self.relief_seconds), format_duration(self.work_seconds)));
+        }
+        for (number, segment) in self.sequence.iter().enumerate().map(|(i, segment)| (i + 1, segment)) {
+            if segment.name.trim().is_empty() {
+                violations.push(format!("Segment {number} of the custom sequence has no name"));
+            }
+            if !(1..=MAX_SEGMENT_SECONDS).contains(&segment.seconds) {
+                violations.push(format!("Segment {number} ({}) must last between 1s and {} (is {})",
+                    segment.name, format_duration(MAX_SEGMENT_SECONDS), format_duration(segment.seconds)));
+            }
+        }
+        if !self.sequence.is_empty() && !self.sequence.iter().any(|segment| segment.session == TimerSession::Working) {
+            violations.push("The custom sequence needs at least one work segment".to_string());
+        }
+        violations
+    }
+
+    // Why the setting `key` is out of its bounds, if it is
+    pub fn limit_violation(&self, key: &str) -> Option<String> {
+        let limit = LIMITS.iter().find(|limit| limit.key == key)?;
+        let value = (limit.value)(self);
+        if (limit.min..=limit.max).contains(&value) {
+            return None;
+        }
+        let describe = |value: u32| if key.ends_with("_seconds") { format_duration(value) } else { value.to_string() };
+        Some(format!("{key} must be between {} and {} (is {})", describe(limit.min), describe(limit.max), describe(value)))
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let violations = self.violations();
+        if violations.is_empty() { Ok(()) } else { Err(ConfigError::Invalid(violations)) }
+    }
+}
+
+impl fmt::Display for Settings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.sequence.is_empty() {
+            write!(f, "Work for {} minutes, Rest for {} seconds, for {} cycles, then break for {} minutes", 
+                self.work_seconds/60, self.relief_seconds, self.work_relief_cycles, self.break_seconds/60
+            )?;
+        } else {
+            let steps: Vec<String> = self.sequence.iter().map(Segment::to_string).collect();
+            write!(f, "Custom sequence: {}", steps.join(" → "))?;
+        }
+        if self.auto_start_work || self.auto_start_relief || self.auto_start_break {
+            write!(f, "\nAuto-start: work {}, relief {}, break {} (after {} seconds)",
+                on_off(self.auto_start_work), on_off(self.auto_start_relief), on_off(self.auto_start_break),
+                self.auto_advance_grace_seconds
+            )?;
+        }
+        write!(f, "\nAlert: {}", self.alert_sound)?;
+        if self.alert_repeat_seconds > 0 {
+            write!(f, ", repeating every {} seconds", self.alert_repeat_seconds)?;
+        }
+        if self.max_snoozes > 0 {
+            write!(f, "\nSnooze: {}, up to {} times a session", format_duration(self.snooze_seconds), self.max_snoozes)?;
+        }
+        write!(f, "\nDesktop notifications: {}", on_off(self.desktop_notifications))?;
+        write!(f, "\nAfter sleeping over {}: {}", format_duration(self.suspend_threshold_seconds), self.suspend_policy.as_str())?;
+        Ok(())
+    }
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value {"on"} else {"off"}
+}
+
+pub const DEFAULT_PROFILE: &str = "default";
+// Schema of the config file. Bump it whenever the layout changes, and add a migration from the version before
+pub const CONFIG_VERSION: u32 = 2;
+
+const LOCK_FILE_NAME: &str = "config.lock";
+
+const HOUR: u32 = 60 * 60;
+
+// The lowest and highest values a numeric setting may take
+struct Limit {
+    key: &'static str,
+    value: fn(&Settings) -> u32,
+    min: u32,
+    max: u32,
+}
+
+const LIMITS: [Limit; 9] = [
+    Limit { key: "work_seconds", value: |s| s.work_seconds, min: 1, max: 4 * HOUR },
+    Limit { key: "relief_seconds", value: |s| s.relief_seconds, min: 1, max: HOUR },
+    Limit { key: "break_seconds", value: |s| s.break_seconds, min: 1, max: 4 * HOUR },
+    Limit { key: "work_relief_cycles", value: |s| s.work_relief_cycles, min: 1, max: 24 },
+    Limit { key: "auto_advance_grace_seconds", value: |s| s.auto_advance_grace_seconds, min: 0, max: HOUR },
+    Limit { key: "alert_repeat_seconds", value: |s| s.alert_repeat_seconds, min: 0, max: HOUR },
+    Limit { key: "snooze_seconds", value: |s| s.snooze_seconds, min: 1, max: HOUR },
+    Limit { key: "max_snoozes", value: |s| s.max_snoozes, min: 0, max: 10 },
+    // Much lower and a busy machine scheduling the timer late would pass for a sleep
+    Limit { key: "suspend_threshold_seconds", value: |s| s.suspend_threshold_seconds, min: 10, max: HOUR },
+];
+
+const MAX_SEGMENT_SECONDS: u32 = 4 * HOUR;
+
+// Each migration takes a config from version n (at index n-1) up to version n+1
+const MIGRATIONS: [fn(Value) -> Value; 1] = [migrate_v1_to_v2];
+
+#[derive(Debug)]
+pub enum ConfigError {
+    // The platform gave us no config directory for this user
+    DirUnresolved,
+    Io { path: PathBuf, source: io::Error },
+    // Lines and columns count from 1, as editors show them
+    Parse { path: PathBuf, line: usize, column: usize, message: String },
+    // The config was read, but what it asks for can't be used
+    Validation(String),
+    // A profile named for a change is missing, taken, or can't be changed that way
+    Profile(String),
+    // The config couldn't be put into the file's format
+    Serialize { format: ConfigFormat, message: String },
+    // Converting the config to the format it is already in
+    AlreadyInFormat(ConfigFormat),
+    // The file was written by a newer version of the app, in a layout this one doesn't know
+    NewerVersion { path: PathBuf, version: u32 },
+    // Settings that break one or more rules, with every rule they break
+    Invalid(Vec<String>),
+    // Override variables in the environment that couldn't be used, with why. The file is not to blame
+    Environment(Vec<String>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DirUnresolved => write!(f, "Could not determine correct config directory"),
+            Self::Io { path, source } => write!(f, "Could not access {}: {}", path.display(), source),
+            Self::Parse { path, line, column, message } => {
+                write!(f, "{} is not valid (line {}, column {}): {}", path.display(), line, column, message)
+            }
+            Self::Validation(message) | Self::Profile(message) => write!(f, "{message}"),
+            Self::Serialize { format, message } => {
+                write!(f, "Could not write the config as {}: {}", format.as_str(), message)
+            }
+            Self::AlreadyInFormat(format) => write!(f, "The config is already in {}", format.as_str()),
+            Self::NewerVersion { path, version } => write!(
+                f,
+                "{} was written by a newer version of RustyPomodoro (config version {}, newest supported is {}). \
+                 It is left as it is, and settings won't be saved until it is readable",
+                path.display(), version, CONFIG_VERSION
+            ),
+            Self::Invalid(violations) => {
+                write!(f, "The settings are not valid:")?;
+                for violation in violations {
+                    write!(f, "\n  - {violation}")?;
+                }
+                Ok(())
+            }
+            Self::Environment(errors) => {
+                write!(f, "Ignored {}* environment variables (fix or unset them to stop this warning):", config_layers::ENV_PREFIX)?;
+                for error in errors {
+                    write!(f, "\n  - {error}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+// io::Error can't be cloned, so a copy carries its kind and message
+impl Clone for ConfigError {
+    fn clone(&self) -> Self {
+        match self {
+            Self::DirUnresolved => Self::DirUnresolved,
+            Self::Io { path, source } => Self::io(path, io::Error::new(source.kind(), source.to_string())),
+            Self::Parse { path, line, column, message } => {
+                Self::Parse { path: path.clone(), line: *line, column: *column, message: message.clone() }
+            }
+            Self::Validation(message) => Self::Validation(message.clone()),
+            Self::Profile(message) => Self::Profile(message.clone()),
+            Self::Serialize { format, message } => Self::Serialize { format: *format, message: message.clone() },
+            Self::AlreadyInFormat(format) => Self::AlreadyInFormat(*format),
+            Self::NewerVersion { path, version } => Self::NewerVersion { path: path.clone(), version: *version },
+            Self::Invalid(violations) => Self::Invalid(violations.clone()),
+            Self::Environment(errors) => Self::Environment(errors.clone()),
+        }
+    }
+}
+
+impl ConfigError {
+    fn io(path: &Path, source: io::Error) -> Self {
+        Self::Io { path: path.to_path_buf(), source }
+    }
+}
+
+// Everything the config file holds: the named profiles, and which of them is in use
+#[derive(Serialize, Deserialize)]
+struct ConfigFile {
+    version: u32,
+    active_profile: String,
+    profiles: BTreeMap<String, Settings>,
+    // Settings of the active profile the file leaves out, so they take their default
+    #[serde(skip)]
+    defaulted: BTreeSet<String>,
+}
+
 pub struct ConfigManager{
+    // Where the config is read from and saved to. None if there is nowhere to keep it
+    path: Option<PathBuf>,
+    // Whether the path was given on the command line rather than found in the config directory
+    path_given: bool,
+    // Set when the file couldn't be read, couldn't be backed up before being replaced, or belongs to a newer
+    // version, with why. Writing to it then could lose the user's settings, so saves are refused until it
+    // reloads cleanly
+    blocked: Option<ConfigError>,
+    active_profile: String,
+    profiles: BTreeMap<String, Settings>,
+    // Working copy of the active profile, written back to it on save
     settings: Settings,
+    defaulted: BTreeSet<String>,
+    // Environment and command line overrides, lowest precedence first, and the settings they make
+    overrides: Vec<Override>,
+    effective: Settings,
+    // Environment variables that couldn't be taken as overrides, with why
+    env_errors: Vec<String>,
 }
 
 impl ConfigManager{
     pub fn new() -> Self {
         Self { 
-            settings: Settings::default()
+            path: None,
+            active_profile: DEFAULT_PROFILE.to_string(),
+            path_given: false,
+            blocked: None,
+            profiles: BTreeMap::new(),
+            settings: Settings::default(),
+            defaulted: BTreeSet::new(),
+            overrides: Vec::new(),
+            effective: Settings::default(),
+            env_errors: Vec::new(),
         }
     }
 
-    pub fn build(&mut self){
-        let result = ConfigManager::get_settings_from_file();
-        match result {
-            Ok(v) => {
-                // Modify current settings
-                self.settings = v;
+    // Load the saved config from `path`, or the config directory if not given, with the environment's
+    // overrides and then `cli_overrides` over it. On error the defaults are in use, so the app can carry on
+    // and report it. Environment variables that can't be used are left out, see env_error
+    pub fn build(&mut self, path: Option<PathBuf>, cli_overrides: Vec<Override>) -> Result<(), ConfigError> {
+        let (mut overrides, env_errors) = config_layers::env_overrides(std::env::vars());
+        overrides.extend(cli_overrides);
+        self.overrides = overrides;
+        self.env_errors = env_errors;
+        self.path_given = path.is_some();
+
+        self.use_defaults();
+        match path.map_or_else(Self::get_config_file_path, Ok) {
+            Ok(path) => self.load(path),
+            Err(e) => {
+                // Even without a config file the overrides apply
+                self.refresh();
+                Err(e)
             }
-            Err(_) => {
-                // Create default settings. Also attempt to save the settings to a json file. If save fails nothing changes.
-                self.settings = Settings {work_seconds: 20*60, relief_seconds: 20, break_seconds: 5*60, work_relief_cycles: 2};
-                self.save_settings_to_json();
+        }
+    }
+
+    // The environment variables left out when the config was built, if there were any
+    pub fn env_error(&self) -> Option<ConfigError> {
+        (!self.env_errors.is_empty()).then(|| ConfigError::Environment(self.env_errors.clone()))
+    }
+
+    fn load(&mut self, path: PathBuf) -> Result<(), ConfigError> {
+        let loaded = self.read_file(path);
+        self.refresh();
+        loaded?;
+        // Nothing is written, so the file can be fixed by hand or by saving valid settings over it
+        if let Err(e) = self.effective.validate() {
+            // The overrides still apply, over the defaults rather than the settings that broke the rules
+            self.effective = config_layers::layered(&Settings::default(), &self.overrides);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn read_file(&mut self, path: PathBuf) -> Result<(), ConfigError> {
+        self.path = Some(path.clone());
+        self.blocked = None;
+        // Hold the lock until any rewrite below is done. Without one (e.g. a read-only directory) we can
+        // still read the file
+        let _lock = lock_config(&path).ok();
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                // First run: start the file with the defaults
+                self.use_defaults();
+                return write_config(&path, &self.snapshot());
+            }
+            Err(e) => {
+                // Leave a file we can't read alone, it may well be fine once the problem is fixed
+                self.use_defaults();
+                let error = ConfigError::io(&path, e);
+                self.blocked = Some(error.clone());
+                return Err(error);
+            }
+        };
+
+        match parse_config(&path, &contents) {
+            Ok((config, upgraded_from)) => {
+                self.settings = config.profiles[&config.active_profile].clone();
+                self.active_profile = config.active_profile;
+                self.profiles = config.profiles;
+                self.defaulted = config.defaulted;
+                // Keep the old file around before rewriting it in the current layout
+                if let Some(version) = upgraded_from {
+                    backup(&path, &format!("v{version}"))?;
+                    write_config(&path, &self.snapshot())?;
+                    self.defaulted.clear();
+                }
+                Ok(())
+            }
+            Err(e @ ConfigError::NewerVersion { .. }) => {
+                // Nothing here can be migrated down, and the newer version still needs the file as it is
+                self.use_defaults();
+                self.blocked = Some(e.clone());
+                Err(e)
             }
+            Err(e) => {
+                // Never lose the user's file: copy it aside before starting again from defaults. If even that
+                // fails, don't write over it
+                self.use_defaults();
+                if let Err(backup_error) = backup(&path, &format!("unreadable-{}", unix_now())) {
+                    self.blocked = Some(backup_error.clone());
+                    return Err(backup_error);
+                }
+                write_config(&path, &self.snapshot())?;
+                Err(e)
+            }
+        }
+    }
+
+    fn use_defaults(&mut self) {
+        self.active_profile = DEFAULT_PROFILE.to_string();
+        self.settings = Settings::default();
+        self.profiles = BTreeMap::from([(self.active_profile.clone(), self.settings.clone())]);
+        self.defaulted.clear();
+    }
+
+    // Work out the settings in effect after the saved ones change
+    fn refresh(&mut self) {
+        self.effective = config_layers::layered(&self.settings, &self.overrides);
+    }
+
+    // Read the config again after it changed on disk. Settings that don't validate are rejected, leaving the
+    // ones in use untouched. Returns whether anything in the active profile changed
+    pub fn reload(&mut self) -> Result<bool, ConfigError> {
+        self.resolve_path();
+        let path = self.path.clone().ok_or(ConfigError::DirUnresolved)?;
+        let contents = fs::read_to_string(&path).map_err(|e| ConfigError::io(&path, e))?;
+        let (config, _) = parse_config(&path, &contents)?;
+        let settings = config.profiles[&config.active_profile].clone();
+        let effective = config_layers::layered(&settings, &self.overrides);
+        effective.validate()?;
+
+        let changed = config.active_profile != self.active_profile || effective != self.effective;
+        // The file reads fine now, so it can be written to again
+        self.blocked = None;
+        self.active_profile = config.active_profile;
+        self.profiles = config.profiles;
+        self.defaulted = config.defaulted;
+        self.settings = settings;
+        self.effective = effective;
+        Ok(changed)
+    }
+
+    // The file the config is read from and saved to, once one has been found or given
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    // Files whose changes could change the config: the one given on the command line, or otherwise every file
+    // in the config directory that could be picked up as the config
+    pub fn watched_paths(&self) -> Vec<PathBuf> {
+        match &self.path {
+            Some(path) if self.path_given => vec![path.clone()],
+            Some(path) => path.parent().map(|dir| config_candidates(dir).collect()).unwrap_or_default(),
+            None => Vec::new(),
         }
     }
 
+    // Without a path on the command line, the config is whichever file the config directory holds. Another
+    // instance converting it to another format changes which that is
+    fn resolve_path(&mut self) {
+        if self.path_given {
+            return;
+        }
+        let found = match self.path.as_deref().and_then(Path::parent) {
+            Some(dir) => find_config_file(dir),
+            None => return,
+        };
+        if self.path.as_ref() != Some(&found) {
+            self.path = Some(found);
+            self.blocked = None;
+        }
+    }
+
+    // The settings in effect, with any overrides applied
     pub fn get_settings(&self) -> &Settings {
-        &self.settings
+        &self.effective
     }
 
-    pub fn get_mut_settings(&mut self) -> &mut Settings {
-        &mut self.settings
+    // The active profile's settings as saved, with the overrides applied, for editing. Unlike get_settings these
+    // may not validate, so that a file with mistakes in it can be fixed without losing the rest of it
+    pub fn settings_to_edit(&self) -> Settings {
+        config_layers::layered(&self.settings, &self.overrides)
     }
 
-    pub fn save(&self) {
-        self.save_settings_to_json();
+    // Take edited settings as the active profile's. A value the user left as an override set it is kept as
+    // it was saved, so overrides never find their way into the file
+    pub fn set_settings(&mut self, settings: Settings) {
+        let effective = config_layers::settings_map(&self.settings_to_edit());
+        let mut saved = config_layers::settings_map(&self.settings);
+        for (key, value) in config_layers::settings_map(&settings) {
+            let overridden = self.overrides.iter().any(|o| o.key == key);
+            if !overridden || effective.get(&key) != Some(&value) {
+                saved.insert(key, value);
+            }
+        }
+        self.settings = serde_json::from_value(Value::Object(saved)).unwrap_or(settings);
+        self.refresh();
     }
 
-    fn get_config_file_path() -> Result<PathBuf, Box<dyn Error>> {
-        let proj_dirs = ProjectDirs::from("com", "aaadam3042", "rustypomodoro")
-            .ok_or("Could not determine correct config directory")?;
-        let config_dir = proj_dirs.config_dir();
-        create_dir_all(config_dir)?;
-        Ok(config_dir.join("config.json"))
+    // Use these settings for this run only, without saving them
+    pub fn use_for_run(&mut self, settings: Settings) {
+        self.effective = settings;
     }
 
-    fn get_settings_from_file() -> Result<Settings, Box<dyn Error>> {
-        let path = Self::get_config_file_path()?;
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
-        let data: Settings = serde_json::from_reader(reader)?;
-        Ok(data)
+    // Every setting in effect, with the layer its value came from
+    pub fn origins(&self) -> Vec<(String, Value, Origin)> {
+        config_layers::settings_map(&self.effective).into_iter().map(|(key, value)| {
+            let origin = match self.overrides.iter().rev().find(|o| o.key == key) {
+                Some(layer) => layer.origin.clone(),
+                None => match &self.path {
+                    Some(path) if self.blocked.is_none() && !self.defaulted.contains(&key) => Origin::File(path.clone()),
+                    _ => Origin::Default,
+                },
+            };
+            (key, value, origin)
+        }).collect()
     }
 
-    fn save_settings_to_json(&self) {
-        // Perform serialization and save here - failures will return early and not interrupt app function
-        let json_string = match serde_json::to_string_pretty(&self.settings){
-            Ok(s) => s,
-            Err(e) => {
-                eprintln!("Warning: could not serialize settings: {}", e);
-                return;
+    // Arguments that bring another instance, such as the daemon, up with the same config and overrides
+    pub fn layer_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if self.path_given
+            && let Some(path) = &self.path {
+            args.extend(["--config".to_string(), path.display().to_string()]);
+        }
+        for layer in &self.overrides {
+            if let Origin::Cli(arg) = &layer.origin {
+                args.extend(["--set".to_string(), arg.clone()]);
             }
-        };
-    
-        let path = match Self::get_config_file_path() {
-            Ok(p) => p,
-            Err(e) => {
-                eprintln!("Warning: Could not determine correct config directory: {}", e);
-                return;
+        }
+        args
+    }
+
+    // Save the active profile's settings. Other profiles are left as they are on disk, so changes made to
+    // them by other instances are kept
+    pub fn save(&mut self) -> Result<(), ConfigError> {
+        let (name, settings) = (self.active_profile.clone(), self.settings.clone());
+        self.update(|config| {
+            config.profiles.insert(name, settings);
+            Ok(())
+        })?;
+        // Our copy of the profile has to follow too, or switching away and back would bring the old settings back
+        self.profiles.insert(self.active_profile.clone(), self.settings.clone());
+        Ok(())
+    }
+
+    pub fn active_profile(&self) -> &str {
+        &self.active_profile
+    }
+
+    pub fn profile_names(&self) -> impl Iterator<Item = &str> {
+        self.profiles.keys().map(String::as_str)
+    }
+
+    // A profile's settings with the overrides applied
+    pub fn profile_settings(&self, name: &str) -> Option<Settings> {
+        if name == self.active_profile {
+            return Some(self.effective.clone());
+        }
+        self.profiles.get(name).map(|settings| config_layers::layered(settings, &self.overrides))
+    }
+
+    fn saved_settings(&self, name: &str) -> Option<&Settings> {
+        if name == self.active_profile {
+            return Some(&self.settings);
+        }
+        self.profiles.get(name)
+    }
+
+    // Make another profile the active one. The current profile's unsaved changes are dropped
+    pub fn switch_profile(&mut self, name: &str) -> Result<(), ConfigError> {
+        let settings = self.profiles.get(name).ok_or_else(|| unknown_profile(name))?.clone();
+        config_layers::layered(&settings, &self.overrides).validate()?;
+        let switched = name.to_string();
+        self.update(|config| {
+            // Another instance may have deleted it meanwhile
+            config.profiles.entry(switched.clone()).or_insert(settings);
+            config.active_profile = switched;
+            Ok(())
+        })?;
+        self.active_profile = name.to_string();
+        self.settings = self.profiles[name].clone();
+        self.refresh();
+        Ok(())
+    }
+
+    pub fn create_profile(&mut self, name: &str) -> Result<(), ConfigError> {
+        let name = self.new_profile_name(name)?;
+        self.update(|config| insert_new_profile(config, name, Settings::default()))
+    }
+
+    pub fn clone_profile(&mut self, from: &str, to: &str) -> Result<(), ConfigError> {
+        let settings = self.saved_settings(from).ok_or_else(|| unknown_profile(from))?.clone();
+        let to = self.new_profile_name(to)?;
+        self.update(|config| insert_new_profile(config, to, settings))
+    }
+
+    pub fn rename_profile(&mut self, from: &str, to: &str) -> Result<(), ConfigError> {
+        let to = self.new_profile_name(to)?;
+        if !self.profiles.contains_key(from) {
+            return Err(unknown_profile(from));
+        }
+        // Renamed before the update, which refreshes our profiles under the active profile's name
+        let was_active = self.active_profile == from;
+        if was_active {
+            self.active_profile = to.clone();
+        }
+        let renamed = to.clone();
+        let result = self.update(|config| {
+            let settings = config.profiles.remove(from).ok_or_else(|| unknown_profile(from))?;
+            insert_new_profile(config, renamed.clone(), settings)?;
+            if config.active_profile == from {
+                config.active_profile = renamed;
             }
-        };
+            Ok(())
+        });
+        if result.is_err() && was_active {
+            self.active_profile = from.to_string();
+        }
+        result
+    }
 
-        let mut file = match File::create(&path) {
-            Ok(f) => f,
-            Err(e) => {
-                eprintln!("Warning: Could not create appropriate config file: {}", e);
-                return;
+    pub fn delete_profile(&mut self, name: &str) -> Result<(), ConfigError> {
+        if name == self.active_profile {
+            return Err(ConfigError::Profile(format!("Profile '{name}' is in use. Switch to another profile before deleting it")));
+        }
+        if !self.profiles.contains_key(name) {
+            return Err(unknown_profile(name));
+        }
+        self.update(|config| {
+            if config.active_profile == name {
+                return Err(ConfigError::Profile(format!("Profile '{name}' has been made active elsewhere")));
             }
+            config.profiles.remove(name);
+            Ok(())
+        })
+    }
+
+    fn new_profile_name(&self, name: &str) -> Result<String, ConfigError> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(ConfigError::Profile("Profile name is empty".to_string()));
+        }
+        if self.profiles.contains_key(name) {
+            return Err(ConfigError::Profile(format!("Profile '{name}' already exists")));
+        }
+        Ok(name.to_string())
+    }
+
+    pub fn get_project_dirs() -> Option<ProjectDirs> {
+        ProjectDirs::from("com", "aaadam3042", "rustypomodoro")
+    }
+
+    pub fn get_config_file_path() -> Result<PathBuf, ConfigError> {
+        let proj_dirs = Self::get_project_dirs().ok_or(ConfigError::DirUnresolved)?;
+        let config_dir = proj_dirs.config_dir();
+        create_dir_all(config_dir).map_err(|e| ConfigError::io(config_dir, e))?;
+        Ok(find_config_file(config_dir))
+    }
+
+    // Rewrite the config in another format, replacing the file it was in. Returns the new file's path
+    pub fn convert(&mut self, format: ConfigFormat) -> Result<PathBuf, ConfigError> {
+        let _lock = lock_config(&self.writable_path()?)?;
+        self.resolve_path();
+        let from = self.writable_path()?;
+        if ConfigFormat::from_path(&from) == format {
+            return Err(ConfigError::AlreadyInFormat(format));
+        }
+        let to = from.with_file_name(format.file_name());
+
+        let contents = fs::read_to_string(&from).map_err(|e| ConfigError::io(&from, e))?;
+        let (config, _) = parse_config(&from, &contents)?;
+        write_config(&to, &config)?;
+        // Left in place, the old file could still be picked up ahead of the new one
+        backup(&from, "converted")?;
+        fs::remove_file(&from).map_err(|e| ConfigError::io(&from, e))?;
+        self.path = Some(to.clone());
+        Ok(to)
+    }
+
+    // The file to write to, or why it can't be written to
+    fn writable_path(&self) -> Result<PathBuf, ConfigError> {
+        if let Some(e) = &self.blocked {
+            return Err(e.clone());
+        }
+        self.path.clone().ok_or(ConfigError::DirUnresolved)
+    }
+
+    // Everything this instance holds, as it would be written out
+    fn snapshot(&self) -> ConfigFile {
+        let mut profiles = self.profiles.clone();
+        profiles.insert(self.active_profile.clone(), self.settings.clone());
+        ConfigFile { version: CONFIG_VERSION, active_profile: self.active_profile.clone(), profiles, defaulted: BTreeSet::new() }
+    }
+
+    // Apply a change to the config as it is on disk, holding the lock from reading it to writing it back so
+    // no other instance can save in between. Our copy of the profiles is refreshed from the result
+    fn update(&mut self, change: impl FnOnce(&mut ConfigFile) -> Result<(), ConfigError>) -> Result<(), ConfigError> {
+        // Every format shares the lock, so the file can't be converted between finding it and writing it
+        let _lock = lock_config(&self.writable_path()?)?;
+        self.resolve_path();
+        let path = self.writable_path()?;
+        let mut config = match fs::read_to_string(&path) {
+            Ok(contents) => parse_config(&path, &contents)?.0,
+            // Deleted from under us, so ours is all there is
+            Err(e) if e.kind() == io::ErrorKind::NotFound => self.snapshot(),
+            Err(e) => return Err(ConfigError::io(&path, e)),
         };
+        change(&mut config)?;
+        write_config(&path, &config)?;
+        self.profiles = config.profiles;
+        // Another instance may have deleted the profile in use, which stays ours until we switch away
+        self.profiles.entry(self.active_profile.clone()).or_insert_with(|| self.settings.clone());
+        // Every setting is written out now
+        self.defaulted.clear();
+        Ok(())
+    }
+
+}
+
+fn unknown_profile(name: &str) -> ConfigError {
+    ConfigError::Profile(format!("No profile named '{name}'"))
+}
+
+fn insert_new_profile(config: &mut ConfigFile, name: String, settings: Settings) -> Result<(), ConfigError> {
+    if config.profiles.contains_key(&name) {
+        return Err(ConfigError::Profile(format!("Profile '{name}' already exists")));
+    }
+    config.profiles.insert(name, settings);
+    Ok(())
+}
 
-        if let Err(e) = file.write_all(json_string.as_bytes()) {
-            eprintln!("Warning: could not write settings to file: {}", e);
+// The config file in `dir`, going by the format precedence. A new config starts out as JSON
+fn find_config_file(dir: &Path) -> PathBuf {
+    config_candidates(dir)
+        .find(|path| path.exists())
+        .unwrap_or_else(|| dir.join(ConfigFormat::Json.file_name()))
+}
+
+// Every file in `dir` that could hold the config, in order of precedence
+fn config_candidates(dir: &Path) -> impl Iterator<Item = PathBuf> {
+    ConfigFormat::PRECEDENCE.iter()
+        .flat_map(|format| format.file_names())
+        .map(move |name| dir.join(name))
+}
+
+// Take the advisory lock that every writer of the config holds, released when the returned file is dropped.
+// It lives in a file of its own, because the config itself is replaced on every write
+fn lock_config(path: &Path) -> Result<File, ConfigError> {
+    // Shared by every format, so converting between them is covered too
+    let lock_path = path.with_file_name(LOCK_FILE_NAME);
+    let file = OpenOptions::new().create(true).truncate(false).write(true).open(&lock_path)
+        .map_err(|e| ConfigError::io(&lock_path, e))?;
+    file.lock().map_err(|e| ConfigError::io(&lock_path, e))?;
+    Ok(file)
+}
+
+// Write the whole config to a temporary file, flush it to disk, then rename it over the old one. Readers see
+// either the old file or the new one, never part of one, even if we crash half way
+fn write_config(path: &Path, config: &ConfigFile) -> Result<(), ConfigError> {
+    let contents = ConfigFormat::from_path(path).serialize(config)?;
+    let temp_path = sibling(path, &format!(".tmp-{}", std::process::id()));
+    let write = || -> io::Result<()> {
+        let mut file = File::create(&temp_path)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()
+    };
+    if let Err(e) = write() {
+        let _ = fs::remove_file(&temp_path);
+        return Err(ConfigError::io(&temp_path, e));
+    }
+    fs::rename(&temp_path, path).map_err(|e| ConfigError::io(path, e))?;
+    // Make the rename itself durable. Not every platform can open a directory, so this is best-effort
+    if let Some(dir) = path.parent()
+        && let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+// The path with `suffix` added to its file name
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+// Read a config written by any version so far, migrating it to the current layout. Also returns the version
+// the file was written with if it isn't in the current layout, so it can be rewritten
+fn parse_config(path: &Path, contents: &str) -> Result<(ConfigFile, Option<u32>), ConfigError> {
+    let mut value = ConfigFormat::from_path(path).parse(path, contents)?;
+    let (version, versioned) = match value.get("version") {
+        Some(version) => {
+            let version = version.as_u64().and_then(|v| u32::try_from(v).ok())
+                .ok_or_else(|| ConfigError::Validation(format!("Invalid config version {version}")))?;
+            (version, true)
         }
+        // Profiles came in before the version field, single settings before that
+        None if value.get("profiles").is_some() => (2, false),
+        None => (1, false),
+    };
+    if version == 0 {
+        return Err(ConfigError::Validation("Invalid config version 0".to_string()));
+    }
+    if version > CONFIG_VERSION {
+        return Err(ConfigError::NewerVersion { path: path.to_path_buf(), version });
+    }
+
+    for migration in &MIGRATIONS[version as usize - 1..] {
+        value = migration(value);
+    }
+    if let Value::Object(map) = &mut value {
+        map.insert("version".to_string(), Value::from(CONFIG_VERSION));
     }
 
+    // Values have moved around by now, so a position within the original text can't be given
+    let mut config = ConfigFile::deserialize(&value)
+        .map_err(|e| ConfigError::Validation(format!("{} is not valid: {}", path.display(), e)))?;
+    if !config.profiles.contains_key(&config.active_profile) {
+        return Err(ConfigError::Validation(format!(
+            "{} makes '{}' the active profile, but has no profile by that name", path.display(), config.active_profile
+        )));
+    }
+    if let Some(given) = value["profiles"][&config.active_profile].as_object() {
+        config.defaulted = config_layers::settings_map(&Settings::default()).into_iter()
+            .map(|(key, _)| key)
+            .filter(|key| !given.contains_key(key))
+            .collect();
+    }
+    let upgraded_from = (!versioned || version < CONFIG_VERSION).then_some(version);
+    Ok((config, upgraded_from))
+}
+
+// Version 1 held a single set of settings, which becomes the default profile
+fn migrate_v1_to_v2(settings: Value) -> Value {
+    json!({
+        "version": 2,
+        "active_profile": DEFAULT_PROFILE,
+        "profiles": { DEFAULT_PROFILE: settings },
+    })
+}
+
+// Copy the file next to itself as <name>.<tag>.bak
+fn backup(path: &Path, tag: &str) -> Result<(), ConfigError> {
+    let backup = sibling(path, &format!(".{tag}.bak"));
+    fs::copy(path, &backup).map(|_| ()).map_err(|e| ConfigError::io(&backup, e))
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Load a fixture as if it were the user's config.json, in a directory of its own
+    fn load_fixture(name: &str) -> (ConfigManager, PathBuf, Result<(), ConfigError>) {
+        let dir = std::env::temp_dir().join(format!("rustypomodoro-config-test-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        create_dir_all(&dir).unwrap();
+        let path = dir.join("config.json");
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/config").join(name);
+        fs::copy(fixture, &path).unwrap();
+
+        let mut config = ConfigManager::new();
+        let result = config.load(path.clone());
+        (config, path, result)
+    }
+
+    fn saved_version(path: &Path) -> u64 {
+        let saved: Value = serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        saved["version"].as_u64().unwrap()
+    }
+
+    fn backups(path: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(path.parent().unwrap()).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| name.ends_with(".bak"))
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn cleanup(path: &Path) {
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn migrates_version_1_files() {
+        let (config, path, result) = load_fixture("v1_original.json");
+        assert!(result.is_ok());
+        let settings = config.get_settings();
+        assert_eq!((settings.work_seconds, settings.relief_seconds, settings.break_seconds, settings.work_relief_cycles), (1500, 30, 900, 4));
+        // Fields the file predates come from the defaults
+        assert!(!settings.desktop_notifications && settings.sequence.is_empty());
+        assert_eq!(config.active_profile(), DEFAULT_PROFILE);
+        assert_eq!(saved_version(&path), CONFIG_VERSION as u64);
+        assert_eq!(backups(&path), vec!["config.json.v1.bak"]);
+        cleanup(&path);
+
+        let (config, path, _) = load_fixture("v1_alerts.json");
+        let settings = config.get_settings();
+        assert!(settings.auto_start_relief && settings.desktop_notifications);
+        assert_eq!((settings.auto_advance_grace_seconds, settings.alert_repeat_seconds), (5, 30));
+        assert_eq!(settings.alert_sound, AlertSound::Tone);
+        cleanup(&path);
+    }
+
+    #[test]
+    fn loads_version_2_files() {
+        let (config, path, _) = load_fixture("v2_unversioned.json");
+        assert_eq!(config.active_profile(), "writing");
+        assert_eq!(config.profile_names().collect::<Vec<_>>(), vec!["default", "writing"]);
+        assert_eq!(config.get_settings().sequence[1], Segment::new("Stretch", TimerSession::Break, 600, true));
+        assert_eq!(saved_version(&path), 2);
+        assert_eq!(backups(&path), vec!["config.json.v2.bak"]);
+        cleanup(&path);
+
+        // Already current, so left as it is
+        let (config, path, result) = load_fixture("v2.json");
+        assert!(result.is_ok());
+        assert_eq!(config.get_settings().work_relief_cycles, 3);
+        assert!(config.get_settings().desktop_notifications);
+        assert!(backups(&path).is_empty());
+        cleanup(&path);
+    }
+
+    #[test]
+    fn converts_between_formats() {
+        let (mut config, json_path, _) = load_fixture("v2.json");
+        let dir = json_path.parent().unwrap().to_path_buf();
+        config.create_profile("coding").unwrap();
+
+        for format in [ConfigFormat::Toml, ConfigFormat::Yaml, ConfigFormat::Json] {
+            let path = config.convert(format).unwrap();
+            assert_eq!(find_config_file(&dir), path);
+            let mut converted = ConfigManager::new();
+            converted.load(path).unwrap();
+            assert_eq!(converted.get_settings(), config.get_settings());
+            assert_eq!(converted.profile_names().collect::<Vec<_>>(), vec!["coding", "default"]);
+        }
+        assert!(matches!(config.convert(ConfigFormat::Json), Err(ConfigError::AlreadyInFormat(ConfigFormat::Json))));
+
+        // TOML wins over the others when there is more than one
+        fs::write(dir.join("config.toml"), "version = 2\nactive_profile = [\n").unwrap();
+        assert_eq!(find_config_file(&dir), dir.join("config.toml"));
+        let mut broken = ConfigManager::new();
+        match broken.load(dir.join("config.toml")) {
+            Err(ConfigError::Parse { line, column, .. }) => assert_eq!((line, column), (2, 19)),
+            result => panic!("Unexpected result: {result:?}"),
+        }
+        cleanup(&json_path);
+    }
+
+    #[test]
+    fn other_instances_follow_a_conversion() {
+        let (mut running, json_path, _) = load_fixture("v2.json");
+        let dir = json_path.parent().unwrap().to_path_buf();
+        let mut converter = ConfigManager::new();
+        converter.load(json_path.clone()).unwrap();
+        let toml_path = converter.convert(ConfigFormat::Toml).unwrap();
+
+        assert!(!running.reload().unwrap());
+        running.set_settings(Settings { work_seconds: 3000, ..running.get_settings().clone() });
+        running.save().unwrap();
+        assert!(!json_path.exists());
+        assert_eq!(running.watched_paths()[0], toml_path);
+        let mut saved = ConfigManager::new();
+        saved.load(find_config_file(&dir)).unwrap();
+        assert_eq!(saved.get_settings().work_seconds, 3000);
+
+        // A .yml config is found too
+        fs::remove_file(&toml_path).unwrap();
+        fs::write(dir.join("config.yml"), "version: 2\nactive_profile: default\nprofiles:\n  default:\n    work_seconds: 1800\n").unwrap();
+        assert!(running.reload().unwrap());
+        assert_eq!(running.get_settings().work_seconds, 1800);
+        assert_eq!(running.path, Some(dir.join("config.yml")));
+        assert!(running.convert(ConfigFormat::Yaml).is_err());
+        cleanup(&json_path);
+    }
+
+    #[test]
+    fn overrides_are_layered_without_being_saved() {
+        let (mut config, path, _) = load_fixture("v2.json");
+        config.overrides = vec![config_layers::cli_override("work=50m").unwrap()];
+        config.refresh();
+        assert_eq!(config.get_settings().work_seconds, 3000);
+
+        let origins = config.origins();
+        let origin = |key: &str| origins.iter().find(|(k, _, _)| k == key).unwrap().2.clone();
+        assert_eq!(origin("work_seconds"), Origin::Cli("work=50m".to_string()));
+        assert_eq!(origin("break_seconds"), Origin::File(path.clone()));
+        assert_eq!(origin("alert_repeat_seconds"), Origin::Default);
+
+        // Editing another setting leaves the saved work length alone
+        config.set_settings(Settings { break_seconds: 600, ..config.get_settings().clone() });
+        config.save().unwrap();
+        let mut saved = ConfigManager::new();
+        saved.load(path.clone()).unwrap();
+        assert_eq!((saved.get_settings().work_seconds, saved.get_settings().break_seconds), (1500, 600));
+        cleanup(&path);
+    }
+
+    #[test]
+    fn invalid_settings_report_every_violation() {
+        let original = fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/config/invalid.json")).unwrap();
+        let (config, path, result) = load_fixture("invalid.json");
+        let Err(ConfigError::Invalid(violations)) = result else { panic!("Unexpected result: {result:?}") };
+        assert_eq!(violations, vec![
+            "work_relief_cycles must be between 1 and 24 (is 0)",
+            "alert_repeat_seconds must be between 0s and 1h (is 24h)",
+            "relief_seconds (15m) must be shorter than work_seconds (10m)",
+        ]);
+        // The defaults run instead, and the file is left for the user to fix
+        assert_eq!(config.get_settings(), &Settings::default());
+        assert_eq!(fs::read_to_string(&path).unwrap(), original);
+        // Overrides still apply over the defaults
+        let mut overridden = ConfigManager::new();
+        overridden.overrides = vec![config_layers::cli_override("work=50m").unwrap()];
+        assert!(matches!(overridden.load(path.clone()), Err(ConfigError::Invalid(_))));
+        assert_eq!(overridden.get_settings(), &Settings { work_seconds: 3000, ..Settings::default() });
+
+        // Fixing the flagged settings keeps every other value from the file
+        let mut config = config;
+        let mut edited = config.settings_to_edit();
+        assert_eq!(edited.violations().len(), 3);
+        let custom = (edited.work_seconds, edited.break_seconds);
+        edited.work_relief_cycles = 2;
+        edited.alert_repeat_seconds = 0;
+        edited.relief_seconds = 20;
+        config.set_settings(edited);
+        config.save().unwrap();
+        assert!(config.get_settings().violations().is_empty());
+        let mut saved = ConfigManager::new();
+        saved.load(path.clone()).unwrap();
+        assert_eq!((saved.get_settings().work_seconds, saved.get_settings().break_seconds), custom);
+
+        let sequence = vec![Segment::new("", TimerSession::Break, 0, false)];
+        let violations = Settings { sequence, ..Settings::default() }.violations();
+        assert_eq!(violations, vec![
+            "Segment 1 of the custom sequence has no name",
+            "Segment 1 () must last between 1s and 4h (is 0s)",
+            "The custom sequence needs at least one work segment",
+        ]);
+        cleanup(&path);
+    }
+
+    #[test]
+    fn reload_takes_valid_changes_only() {
+        let (mut config, path, _) = load_fixture("v2.json");
+        assert!(!config.reload().unwrap());
+
+        let edited = fs::read_to_string(&path).unwrap().replace("\"work_seconds\": 1500", "\"work_seconds\": 1800");
+        fs::write(&path, &edited).unwrap();
+        assert!(config.reload().unwrap());
+        assert_eq!(config.get_settings().work_seconds, 1800);
+
+        fs::write(&path, edited.replace("\"work_relief_cycles\": 3", "\"work_relief_cycles\": 0")).unwrap();
+        assert!(matches!(config.reload(), Err(ConfigError::Invalid(_))));
+        assert_eq!(config.get_settings().work_relief_cycles, 3);
+        cleanup(&path);
+    }
+
+    #[test]
+    fn unreadable_files_are_backed_up_before_defaults_are_written() {
+        let original = fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/config/truncated.json")).unwrap();
+        let (config, path, result) = load_fixture("truncated.json");
+        match result {
+            Err(ConfigError::Parse { line, column, .. }) => assert_eq!((line, column), (3, 13)),
+            result => panic!("Unexpected result: {result:?}"),
+        }
+        assert_eq!(config.get_settings().work_seconds, Settings::default().work_seconds);
+        assert_eq!(saved_version(&path), CONFIG_VERSION as u64);
+
+        let backups = backups(&path);
+        assert_eq!(backups.len(), 1);
+        assert!(backups[0].starts_with("config.json.unreadable-"));
+        assert_eq!(fs::read_to_string(path.with_file_name(&backups[0])).unwrap(), original);
+        cleanup(&path);
+    }
+
+    #[test]
+    fn files_from_newer_versions_are_left_alone() {
+        let original = fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/config/future.json")).unwrap();
+        let (mut config, path, result) = load_fixture("future.json");
+        assert!(matches!(result, Err(ConfigError::NewerVersion { version: 99, .. })));
+        assert_eq!(config.get_settings().work_seconds, Settings::default().work_seconds);
+        assert!(matches!(config.save(), Err(ConfigError::NewerVersion { version: 99, .. })));
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), original);
+        assert!(backups(&path).is_empty());
+        cleanup(&path);
+    }
+
+    #[test]
+    fn saved_edits_survive_switching_profiles() {
+        let (mut config, path, _) = load_fixture("v2.json");
+        config.create_profile("coding").unwrap();
+        config.set_settings(Settings { work_seconds: 3000, ..config.get_settings().clone() });
+        config.save().unwrap();
+
+        config.switch_profile("coding").unwrap();
+        config.switch_profile(DEFAULT_PROFILE).unwrap();
+        assert_eq!(config.get_settings().work_seconds, 3000);
+        let mut saved = ConfigManager::new();
+        saved.load(path.clone()).unwrap();
+        assert_eq!(saved.get_settings().work_seconds, 3000);
+        cleanup(&path);
+    }
+
+    #[test]
+    fn renaming_the_active_profile_leaves_no_trace_of_the_old_name() {
+        let (mut config, path, _) = load_fixture("v2.json");
+        config.create_profile("coding").unwrap();
+        config.rename_profile(DEFAULT_PROFILE, "reading").unwrap();
+        assert_eq!(config.active_profile(), "reading");
+        assert_eq!(config.profile_names().collect::<Vec<_>>(), vec!["coding", "reading"]);
+        assert!(matches!(config.switch_profile(DEFAULT_PROFILE), Err(ConfigError::Profile(_))));
+
+        let mut saved = ConfigManager::new();
+        saved.load(path.clone()).unwrap();
+        assert_eq!(saved.active_profile(), "reading");
+        assert_eq!(saved.profile_names().collect::<Vec<_>>(), vec!["coding", "reading"]);
+        cleanup(&path);
+    }
+
+    #[test]
+    fn saves_are_refused_while_the_file_cannot_be_read() {
+        let dir = std::env::temp_dir().join(format!("rustypomodoro-config-test-{}-blocked", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        // A directory where the file should be can't be read, and mustn't be written over
+        let path = dir.join("config.json");
+        create_dir_all(&path).unwrap();
+
+        let mut config = ConfigManager::new();
+        assert!(matches!(config.load(path.clone()), Err(ConfigError::Io { .. })));
+        assert_eq!(config.path, Some(path.clone()));
+        match config.save() {
+            Err(ConfigError::Io { path: failed, .. }) => assert_eq!(failed, path),
+            result => panic!("Unexpected result: {result:?}"),
+        }
+
+        // Once it can be read again, saving works
+        fs::remove_dir(&path).unwrap();
+        fs::copy(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/config/v2.json"), &path).unwrap();
+        config.reload().unwrap();
+        config.save().unwrap();
+        cleanup(&path);
+    }
+
+    #[test]
+    fn concurrent_writers_keep_each_others_changes() {
+        let (_, path, _) = load_fixture("v2.json");
+        // Each writer loaded the file before the others saved, as separate instances would have
+        let writers: Vec<_> = (0..4).map(|i| {
+            let path = path.clone();
+            std::thread::spawn(move || {
+                let mut config = ConfigManager::new();
+                config.load(path).unwrap();
+                for n in 0..10 {
+                    config.create_profile(&format!("w{i}-{n}")).unwrap();
+                }
+            })
+        }).collect();
+        // A reader never sees a half written file
+        let reader = {
+            let path = path.clone();
+            std::thread::spawn(move || {
+                for _ in 0..200 {
+                    let contents = fs::read_to_string(&path).unwrap();
+                    parse_config(&path, &contents).unwrap();
+                }
+            })
+        };
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        reader.join().unwrap();
+
+        let mut config = ConfigManager::new();
+        config.load(path.clone()).unwrap();
+        assert_eq!(config.profile_names().count(), 1 + 40);
+
+        // Stale copies: one adds a profile, the other then saves its settings. Both changes survive
+        let mut first = ConfigManager::new();
+        first.load(path.clone()).unwrap();
+        let mut second = ConfigManager::new();
+        second.load(path.clone()).unwrap();
+        first.create_profile("coding").unwrap();
+        second.set_settings(Settings { work_seconds: 3000, ..second.get_settings().clone() });
+        second.save().unwrap();
+        assert!(second.profile_names().any(|name| name == "coding"));
+
+        let mut config = ConfigManager::new();
+        config.load(path.clone()).unwrap();
+        assert!(config.profile_names().any(|name| name == "coding"));
+        assert_eq!(config.get_settings().work_seconds, 3000);
+        // Nothing left behind by the writes
+        assert!(fs::read_dir(path.parent().unwrap()).unwrap()
+            .all(|entry| !entry.unwrap().file_name().to_string_lossy().contains(".tmp-")));
+        cleanup(&path);
+    }
 }
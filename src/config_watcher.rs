@@ -0,0 +1,84 @@
+/*
+The config watcher notices when the config file changes on disk, whether edited by hand, synced in from
+elsewhere, saved by another instance, or replaced by one in another format. It only says that something
+changed; reading and checking the new settings is up to the config manager.
+*/
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+// Editors and sync tools differ in how they write files, so rather than relying on filesystem events
+// the file is simply looked at this often
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct ConfigWatcher {
+    changed_rx: Receiver<()>,
+    // Never sent on. Dropping it with the watcher is what stops the thread
+    _stop_tx: Sender<()>,
+}
+
+impl ConfigWatcher {
+    // Watch every one of `paths`, reporting a change to any of them
+    pub fn spawn(paths: Vec<PathBuf>) -> Self {
+        Self::spawn_every(paths, POLL_INTERVAL)
+    }
+
+    fn spawn_every(paths: Vec<PathBuf>, interval: Duration) -> Self {
+        let (changed_tx, changed_rx) = mpsc::channel();
+        let (stop_tx, stop_rx) = mpsc::channel::<()>();
+        thread::spawn(move || {
+            let stamps = || paths.iter().map(|path| stamp(path)).collect::<Vec<_>>();
+            let mut last = stamps();
+            // Wait out the interval, stopping as soon as the watcher is dropped
+            while let Err(RecvTimeoutError::Timeout) = stop_rx.recv_timeout(interval) {
+                let current = stamps();
+                if current != last {
+                    last = current;
+                    let _ = changed_tx.send(());
+                }
+            }
+        });
+        Self { changed_rx, _stop_tx: stop_tx }
+    }
+
+    // Whether the file has changed since the last call
+    pub fn has_changed(&self) -> bool {
+        self.changed_rx.try_iter().count() > 0
+    }
+}
+
+// Enough to tell one version of the file from the next. None while the file doesn't exist
+fn stamp(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_each_change_once() {
+        let path = std::env::temp_dir().join(format!("rustypomodoro-watcher-test-{}.json", std::process::id()));
+        fs::write(&path, "{}").unwrap();
+        let other = path.with_extension("toml");
+        let _ = fs::remove_file(&other);
+        let watcher = ConfigWatcher::spawn_every(vec![other.clone(), path.clone()], Duration::from_millis(10));
+        thread::sleep(Duration::from_millis(50));
+        assert!(!watcher.has_changed());
+
+        fs::write(&path, "{\"version\": 2}").unwrap();
+        thread::sleep(Duration::from_millis(50));
+        assert!(watcher.has_changed());
+        assert!(!watcher.has_changed());
+
+        // A file appearing in another format counts too
+        fs::write(&other, "version = 2").unwrap();
+        thread::sleep(Duration::from_millis(50));
+        assert!(watcher.has_changed());
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(&other);
+    }
+}
@@ -0,0 +1,541 @@
+/*
+The daemon owns the app and its timer, so a session outlives the terminal it was started from.
+It listens on a Unix domain socket and speaks a line based JSON protocol: every request and response is
+one JSON object on its own line, tagged with the protocol version.
+The client half of this module is what the command line uses to talk to it.
+*/
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+
+use serde::{Serialize, Deserialize};
+
+use crate::PomodoroApp;
+use crate::config_manager::{ConfigManager, Settings};
+use crate::timer::{TimerEvent, TimerSession, TimerState};
+
+pub const PROTOCOL_VERSION: u32 = 1;
+
+const SOCKET_NAME: &str = "rustypomodoro.sock";
+// How often the daemon checks the timer for new events between requests
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+// How long to wait for the timer to react to a command before answering with what we have
+const COMMAND_SETTLE: Duration = Duration::from_millis(200);
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(2);
+// Time given to connections to write their last responses before the daemon exits
+const SHUTDOWN_GRACE: Duration = Duration::from_millis(100);
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    Start {
+        #[serde(default, skip_serializing_if = "StartOverrides::is_empty")]
+        overrides: StartOverrides,
+        // Task to record the work sessions against
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        task: Option<u32>,
+        // Profile to take the settings from instead of the active one
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        profile: Option<String>,
+    },
+    Pause,
+    Resume,
+    Next,
+    Skip,
+    Restart,
+    Snooze,
+    Stop,
+    Status,
+    // Keep the connection open and receive a response line for every timer event
+    Subscribe,
+    Shutdown,
+}
+
+// Settings that a start request may change for that run only
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct StartOverrides {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub work_seconds: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relief_seconds: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub break_seconds: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub work_relief_cycles: Option<u32>,
+}
+
+impl StartOverrides {
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn apply(&self, settings: &mut Settings) {
+        if let Some(work) = self.work_seconds { settings.work_seconds = work }
+        if let Some(relief) = self.relief_seconds { settings.relief_seconds = relief }
+        if let Some(break_time) = self.break_seconds { settings.break_seconds = break_time }
+        if let Some(cycles) = self.work_relief_cycles { settings.work_relief_cycles = cycles }
+        // A custom sequence takes the new lengths for every segment of that type
+        for segment in &mut settings.sequence {
+            let length = match segment.session {
+                TimerSession::Working => self.work_seconds,
+                TimerSession::Resting => self.relief_seconds,
+                TimerSession::Break => self.break_seconds,
+            };
+            if let Some(length) = length { segment.seconds = length }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct RequestEnvelope {
+    version: u32,
+    #[serde(flatten)]
+    request: Request,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Response {
+    pub version: u32,
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<TimerStatus>,
+}
+
+impl Response {
+    fn ok(status: TimerStatus) -> Self {
+        Self { version: PROTOCOL_VERSION, ok: true, error: None, status: Some(status) }
+    }
+
+    fn error(message: impl Into<String>) -> Self {
+        Self { version: PROTOCOL_VERSION, ok: false, error: Some(message.into()), status: None }
+    }
+}
+
+// Snapshot of the timer as seen from outside the daemon
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TimerStatus {
+    pub state: TimerState,
+    pub session: TimerSession,
+    #[serde(default)]
+    pub segment: String,
+    pub remaining: u32,
+    pub session_length: u32,
+    pub cycles_complete: u32,
+    pub total_cycles: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_advance_in: Option<u32>,
+}
+
+impl TimerStatus {
+    pub fn idle(total_cycles: u32) -> Self {
+        Self {
+            state: TimerState::Idle,
+            session: TimerSession::Working,
+            segment: String::new(),
+            remaining: 0,
+            session_length: 0,
+            cycles_complete: 0,
+            total_cycles,
+            auto_advance_in: None,
+        }
+    }
+
+    fn update(&mut self, event: TimerEvent) {
+        self.state = event.state;
+        self.session = event.session;
+        self.segment = event.segment;
+        self.remaining = event.remaining;
+        self.session_length = event.session_length;
+        self.cycles_complete = event.cycles_complete;
+        self.auto_advance_in = event.auto_advance_in;
+    }
+}
+
+pub fn get_socket_path() -> Result<PathBuf, String> {
+    let proj_dirs = ConfigManager::get_project_dirs()
+        .ok_or("Could not determine correct runtime directory")?;
+    // Not every platform has a runtime directory, the data directory will do instead
+    let dir = proj_dirs.runtime_dir().unwrap_or(proj_dirs.data_local_dir());
+    fs::create_dir_all(dir).map_err(|e| format!("Could not create {}: {e}", dir.display()))?;
+    Ok(dir.join(SOCKET_NAME))
+}
+
+struct Daemon<'a> {
+    app: &'a mut PomodoroApp,
+    status: TimerStatus,
+    subscribers: Vec<Sender<Response>>,
+    // Set while the timer is one picked up from a session interrupted before the daemon started
+    recovered: bool,
+}
+
+impl Daemon<'_> {
+    // Fold in every pending timer event, giving the timer up to `wait` to produce the first one
+    fn drain_events(&mut self, wait: Duration) {
+        let mut changed = false;
+        let mut timeout = wait;
+        while let Some(event) = self.app.wait_timer_event(timeout) {
+            self.status.update(event);
+            changed = true;
+            timeout = Duration::ZERO;
+        }
+        if self.app.is_timer_disconnected() && self.status.state != TimerState::Idle {
+            self.status = TimerStatus::idle(self.status.total_cycles);
+            changed = true;
+        }
+        if changed {
+            let status = self.status.clone();
+            self.subscribers.retain(|tx| tx.send(Response::ok(status.clone())).is_ok());
+        }
+    }
+
+    // Wait for the timer to leave `before` after a command, so the response reflects it. Countdown ticks
+    // that were already queued are folded in along the way
+    fn settle(&mut self, before: TimerState) {
+        let started = std::time::Instant::now();
+        while self.status.state == before {
+            let Some(left) = COMMAND_SETTLE.checked_sub(started.elapsed()) else { break };
+            self.drain_events(left);
+        }
+    }
+
+    fn handle(&mut self, request: Request) -> Response {
+        // Catch up first, so the request is judged against the latest state
+        self.drain_events(Duration::ZERO);
+        let before = self.status.state;
+        let running = !self.app.is_timer_disconnected();
+        match request {
+            Request::Start { overrides, task, profile } => {
+                if running && self.recovered {
+                    return Response::error("The session interrupted earlier has been picked up, paused. Resume it, or stop it to start another");
+                }
+                if running {
+                    return Response::error("A timer is already running");
+                }
+                self.recovered = false;
+                let settings = match &profile {
+                    Some(name) => self.app.config().profile_settings(name),
+                    None => Some(self.app.get_settings().clone()),
+                };
+                let Some(mut settings) = settings else {
+                    return Response::error(format!("No profile named '{}'", profile.unwrap_or_default()));
+                };
+                overrides.apply(&mut settings);
+                if let Err(e) = settings.validate() {
+                    return Response::error(e.to_string());
+                }
+                self.status = TimerStatus::idle(settings.total_cycles());
+                self.app.set_current_task(task);
+                // Only a timer on the saved settings follows changes to them
+                if overrides.is_empty() && profile.is_none() {
+                    self.app.start_timer();
+                } else {
+                    self.app.start_timer_with(settings);
+                }
+            }
+            Request::Pause | Request::Resume | Request::Next | Request::Skip | Request::Restart | Request::Snooze
+                | Request::Stop if !running => {
+                return Response::error("No timer is running");
+            }
+            Request::Pause => self.app.pause_timer(),
+            Request::Resume => self.app.resume_timer(),
+            Request::Next => self.app.advance_timer(),
+            Request::Skip => self.app.skip_session(),
+            Request::Restart => self.app.restart_session(),
+            Request::Snooze => self.app.snooze_timer(),
+            Request::Stop => self.app.stop_timer(),
+            Request::Status => return Response::ok(self.status.clone()),
+            Request::Subscribe | Request::Shutdown => unreachable!("Handled by the serve loop"),
+        }
+        self.settle(before);
+        Response::ok(self.status.clone())
+    }
+}
+
+// Run the daemon in the foreground until a shutdown request arrives
+pub fn serve(app: &mut PomodoroApp, socket_path: &Path) -> io::Result<()> {
+    let listener = bind(socket_path)?;
+    let (req_tx, req_rx) = mpsc::channel::<(Request, Sender<Response>)>();
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let req_tx = req_tx.clone();
+            thread::spawn(move || handle_connection(stream, req_tx));
+        }
+    });
+
+    app.watch_config();
+    let mut total_cycles = app.get_settings().total_cycles();
+    // Nobody is there to ask, so a session a crash cut short is picked up paused, for clients to resume or stop
+    let recovered = match app.interrupted_session() {
+        Some(snapshot) => {
+            total_cycles = snapshot.settings.total_cycles();
+            app.resume_interrupted(snapshot);
+            app.pause_timer();
+            true
+        }
+        None => false,
+    };
+    let mut daemon = Daemon { app, status: TimerStatus::idle(total_cycles), subscribers: Vec::new(), recovered };
+    loop {
+        daemon.drain_events(Duration::ZERO);
+
+        match req_rx.recv_timeout(POLL_INTERVAL) {
+            Ok((Request::Subscribe, reply_tx)) => {
+                if reply_tx.send(Response::ok(daemon.status.clone())).is_ok() {
+                    daemon.subscribers.push(reply_tx);
+                }
+            }
+            Ok((Request::Shutdown, reply_tx)) => {
+                let _ = reply_tx.send(Response::ok(daemon.status.clone()));
+                break;
+            }
+            Ok((request, reply_tx)) => {
+                let _ = reply_tx.send(daemon.handle(request));
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+
+    let _ = fs::remove_file(socket_path);
+    thread::sleep(SHUTDOWN_GRACE);
+    Ok(())
+}
+
+fn bind(socket_path: &Path) -> io::Result<UnixListener> {
+    if socket_path.exists() {
+        // A socket nobody answers on is left over from a daemon that didn't shut down cleanly
+        if UnixStream::connect(socket_path).is_ok() {
+            return Err(io::Error::new(io::ErrorKind::AddrInUse, "The daemon is already running"));
+        }
+        fs::remove_file(socket_path)?;
+    }
+    UnixListener::bind(socket_path)
+}
+
+fn handle_connection(stream: UnixStream, req_tx: Sender<(Request, Sender<Response>)>) {
+    let Ok(mut writer) = stream.try_clone() else { return };
+    let reader = BufReader::new(stream);
+
+    for line in reader.lines() {
+        let Ok(line) = line else { return };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let request = match parse_request(&line) {
+            Ok(request) => request,
+            Err(e) => {
+                if write_response(&mut writer, &Response::error(e)).is_err() { return }
+                continue;
+            }
+        };
+
+        let subscribing = request == Request::Subscribe;
+        let (reply_tx, reply_rx) = mpsc::channel();
+        if req_tx.send((request, reply_tx)).is_err() {
+            return;
+        }
+        if subscribing {
+            // Stream until the client goes away, dropping reply_rx also unsubscribes us
+            for response in reply_rx {
+                if write_response(&mut writer, &response).is_err() { return }
+            }
+            return;
+        }
+        match reply_rx.recv() {
+            Ok(response) => if write_response(&mut writer, &response).is_err() { return },
+            Err(_) => return,
+        }
+    }
+}
+
+fn parse_request(line: &str) -> Result<Request, String> {
+    #[derive(Deserialize)]
+    struct VersionOnly {
+        version: u32,
+    }
+    let version = serde_json::from_str::<VersionOnly>(line)
+        .map_err(|e| format!("Invalid request: {e}"))?
+        .version;
+    if version != PROTOCOL_VERSION {
+        return Err(format!("Unsupported protocol version {version}, the daemon speaks version {PROTOCOL_VERSION}"));
+    }
+    let envelope: RequestEnvelope = serde_json::from_str(line).map_err(|e| format!("Invalid request: {e}"))?;
+    Ok(envelope.request)
+}
+
+fn write_response(writer: &mut impl Write, response: &Response) -> io::Result<()> {
+    let mut line = serde_json::to_string(response).map_err(io::Error::other)?;
+    line.push('\n');
+    writer.write_all(line.as_bytes())?;
+    writer.flush()
+}
+
+fn connect(socket_path: &Path) -> Result<UnixStream, String> {
+    UnixStream::connect(socket_path)
+        .map_err(|_| "The daemon is not running (start it with `daemon`)".to_string())
+}
+
+fn send_request(stream: &mut UnixStream, request: Request) -> Result<(), String> {
+    let mut line = serde_json::to_string(&RequestEnvelope { version: PROTOCOL_VERSION, request })
+        .map_err(|e| e.to_string())?;
+    line.push('\n');
+    stream.write_all(line.as_bytes()).map_err(|e| format!("Could not talk to the daemon: {e}"))
+}
+
+fn read_response(reader: &mut impl BufRead) -> Result<Option<Response>, String> {
+    let mut line = String::new();
+    match reader.read_line(&mut line) {
+        Ok(0) => Ok(None),
+        Ok(_) => serde_json::from_str(&line).map(Some).map_err(|e| format!("Invalid response from the daemon: {e}")),
+        Err(e) => Err(format!("Could not talk to the daemon: {e}")),
+    }
+}
+
+// Send one request and wait for its answer. A response with ok = false is turned into an error
+pub fn request(socket_path: &Path, request: Request) -> Result<TimerStatus, String> {
+    let mut stream = connect(socket_path)?;
+    send_request(&mut stream, request)?;
+    let response = read_response(&mut BufReader::new(stream))?
+        .ok_or("The daemon closed the connection")?;
+    if !response.ok {
+        return Err(response.error.unwrap_or_else(|| "The daemon refused the request".to_string()));
+    }
+    response.status.ok_or_else(|| "The daemon did not report a status".to_string())
+}
+
+// Follow the timer, calling `on_status` with every change until it returns false or the daemon goes away
+pub fn subscribe(socket_path: &Path, mut on_status: impl FnMut(&TimerStatus) -> bool) -> Result<(), String> {
+    let mut stream = connect(socket_path)?;
+    send_request(&mut stream, Request::Subscribe)?;
+    let mut reader = BufReader::new(stream);
+    while let Some(response) = read_response(&mut reader)? {
+        if let Some(status) = &response.status
+            && !on_status(status) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// Start a daemon that outlives this process, and wait until it is listening. `args` are passed on to it
+// ahead of the daemon command
+pub fn spawn_detached(socket_path: &Path, args: &[String]) -> Result<(), String> {
+    let exe = std::env::current_exe().map_err(|e| format!("Could not find own executable: {e}"))?;
+    Command::new(exe)
+        .args(args)
+        .arg("daemon")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        // Its own process group, so closing the terminal doesn't take it down with us
+        .process_group(0)
+        .spawn()
+        .map_err(|e| format!("Could not start the daemon: {e}"))?;
+
+    let started = std::time::Instant::now();
+    while started.elapsed() < STARTUP_TIMEOUT {
+        if UnixStream::connect(socket_path).is_ok() {
+            return Ok(());
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    Err("The daemon did not start in time".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::{History, SessionOutcome};
+
+    #[test]
+    fn requests_round_trip_with_version() {
+        let line = serde_json::to_string(&RequestEnvelope { version: PROTOCOL_VERSION, request: Request::Pause }).unwrap();
+        assert_eq!(line, r#"{"version":1,"command":"pause"}"#);
+        assert_eq!(parse_request(&line), Ok(Request::Pause));
+
+        let start = r#"{"version":1,"command":"start","overrides":{"work_seconds":1500}}"#;
+        let expected = StartOverrides { work_seconds: Some(1500), ..Default::default() };
+        assert_eq!(parse_request(start), Ok(Request::Start { overrides: expected, task: None, profile: None }));
+        assert_eq!(parse_request(r#"{"version":1,"command":"start"}"#), Ok(Request::Start { overrides: StartOverrides::default(), task: None, profile: None }));
+    }
+
+    #[test]
+    fn serves_commands_and_streams_events() {
+        let socket = std::env::temp_dir().join(format!("rustypomodoro-daemon-test-{}.sock", std::process::id()));
+        let history_path = socket.with_extension("jsonl");
+        let _ = fs::remove_file(&history_path);
+        let server_socket = socket.clone();
+        let server_history = history_path.clone();
+        let server = thread::spawn(move || {
+            let mut app = PomodoroApp::new();
+            app.set_history(History::at(server_history));
+            serve(&mut app, &server_socket)
+        });
+        let started = std::time::Instant::now();
+        while UnixStream::connect(&socket).is_err() {
+            assert!(started.elapsed() < STARTUP_TIMEOUT, "daemon never started listening");
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        assert_eq!(request(&socket, Request::Status).unwrap().state, TimerState::Idle);
+        assert!(request(&socket, Request::Pause).unwrap_err().contains("No timer"));
+
+        let overrides = StartOverrides { work_seconds: Some(60), work_relief_cycles: Some(3), ..Default::default() };
+        let status = request(&socket, Request::Start { overrides: overrides.clone(), task: None, profile: None }).unwrap();
+        assert_eq!((status.state, status.session, status.total_cycles), (TimerState::CountDown, TimerSession::Working, 3));
+        assert!(request(&socket, Request::Start { overrides, task: None, profile: None }).unwrap_err().contains("already running"));
+        let status = request(&socket, Request::Skip).unwrap();
+        assert_eq!((status.state, status.session), (TimerState::CountDown, TimerSession::Resting));
+
+        // The subscriber sees the current status, then every change after it
+        let (seen_tx, seen_rx) = mpsc::channel();
+        let follow_socket = socket.clone();
+        thread::spawn(move || subscribe(&follow_socket, |status| seen_tx.send(status.state).is_ok()));
+        // Countdown ticks may be interleaved, so skip ahead to the state we are after
+        let wait_for = |state| while seen_rx.recv_timeout(STARTUP_TIMEOUT).unwrap() != state {};
+        wait_for(TimerState::CountDown);
+
+        // Stopping while paused asks first, as the session so far goes into history as aborted
+        let status = request(&socket, Request::Pause).unwrap();
+        assert_eq!(status.state, TimerState::Paused);
+        assert!(status.state.is_session_under_way());
+        wait_for(TimerState::Paused);
+        assert_eq!(request(&socket, Request::Stop).unwrap().state, TimerState::Idle);
+        wait_for(TimerState::Idle);
+
+        // So does stopping a session that is counting down
+        let status = request(&socket, Request::Start { overrides: StartOverrides::default(), task: None, profile: None }).unwrap();
+        assert_eq!(status.state, TimerState::CountDown);
+        assert!(status.state.is_session_under_way());
+        assert_eq!(request(&socket, Request::Stop).unwrap().state, TimerState::Idle);
+        let records = History::at(&history_path).records().unwrap();
+        let outcomes: Vec<_> = records.iter().map(|record| (record.session, record.outcome)).collect();
+        assert_eq!(outcomes, vec![
+            (TimerSession::Working, SessionOutcome::Skipped),
+            (TimerSession::Resting, SessionOutcome::Aborted),
+            (TimerSession::Working, SessionOutcome::Aborted),
+        ]);
+        let _ = fs::remove_file(&history_path);
+
+        request(&socket, Request::Shutdown).unwrap();
+        server.join().unwrap().unwrap();
+        assert!(!socket.exists());
+    }
+
+    #[test]
+    fn rejects_other_versions_and_unknown_commands() {
+        assert!(parse_request(r#"{"version":2,"command":"pause"}"#).unwrap_err().contains("version 2"));
+        assert!(parse_request(r#"{"version":1,"command":"explode"}"#).is_err());
+        assert!(parse_request("not json").is_err());
+    }
+}
@@ -0,0 +1,218 @@
+/*
+Export writes session history out in formats other tools can read: CSV for spreadsheets, JSON lines for
+scripts, and iCalendar for importing work sessions into a calendar.
+*/
+use std::io::{self, Write};
+
+use chrono::DateTime;
+use clap::ValueEnum;
+
+use crate::history::SessionRecord;
+use crate::tasks::Task;
+use crate::timer::TimerSession;
+
+const ICS_PRODID: &str = "-//rustypomodoro//session history//EN";
+// Longest a line may be before it is folded, in octets and not counting the CRLF
+const ICS_LINE_OCTETS: usize = 75;
+
+#[derive(Copy, Clone, Debug, PartialEq, ValueEnum)]
+pub enum ExportFormat {
+    /// Comma separated values with a header row
+    Csv,
+    /// One JSON record per line, as stored in the history file
+    Jsonl,
+    /// iCalendar, with one event per session (work sessions unless --session picks another type)
+    Ics,
+}
+
+// Write `records` out in `format`. `session` is the type of session asked for, if any, and `tasks` are used to
+// name the task each session was spent on
+pub fn export(records: &[SessionRecord], format: ExportFormat, session: Option<TimerSession>, tasks: &[Task],
+    out: &mut impl Write) -> io::Result<()> {
+    match format {
+        ExportFormat::Csv => write_csv(records, tasks, out),
+        ExportFormat::Jsonl => write_jsonl(records, out),
+        ExportFormat::Ics => write_ics(records, session.unwrap_or(TimerSession::Working), tasks, out),
+    }
+}
+
+fn write_csv(records: &[SessionRecord], tasks: &[Task], out: &mut impl Write) -> io::Result<()> {
+    writeln!(out, "started_at,ended_at,session,outcome,planned_seconds,actual_seconds,paused_seconds,cycle,task")?;
+    for record in records {
+        let fields = [
+            local_time(record.started_at),
+            local_time(record.ended_at),
+            record.session.as_str().to_string(),
+            record.outcome.as_str().to_string(),
+            record.planned_seconds.to_string(),
+            record.actual_seconds.to_string(),
+            record.paused_seconds.to_string(),
+            (record.cycle + 1).to_string(),
+            record.task.map(|task| task_name(tasks, task)).unwrap_or_default(),
+        ];
+        let row: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        writeln!(out, "{}", row.join(","))?;
+    }
+    Ok(())
+}
+
+fn write_jsonl(records: &[SessionRecord], out: &mut impl Write) -> io::Result<()> {
+    for record in records {
+        serde_json::to_writer(&mut *out, record).map_err(io::Error::other)?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+// iCalendar (RFC 5545) wants CRLF line endings, UTC times, escaped text and long lines folded
+fn write_ics(records: &[SessionRecord], session: TimerSession, tasks: &[Task], out: &mut impl Write) -> io::Result<()> {
+    write!(out, "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:{ICS_PRODID}\r\nCALSCALE:GREGORIAN\r\n")?;
+    for record in records.iter().filter(|r| r.session == session) {
+        let task = record.task.map(|task| task_name(tasks, task));
+        let mut description = format!("{} {} session\n{} planned, {} actual, {} paused\nCycle {}",
+            record.outcome.as_str(), record.session.as_str(),
+            minutes(record.planned_seconds), minutes(record.actual_seconds), minutes(record.paused_seconds),
+            record.cycle + 1
+        );
+        if let Some(task) = &task {
+            description.push_str(&format!("\nTask: {task}"));
+        }
+        let summary = format!("Pomodoro: {}", task.as_deref().unwrap_or(record.session.as_str()));
+        write!(out, "BEGIN:VEVENT\r\n")?;
+        write!(out, "UID:{}-{}@rustypomodoro\r\n", record.started_at, record.session.as_str().to_lowercase())?;
+        write!(out, "DTSTAMP:{}\r\n", utc_time(record.ended_at))?;
+        write!(out, "DTSTART:{}\r\n", utc_time(record.started_at))?;
+        write!(out, "DTEND:{}\r\n", utc_time(record.ended_at))?;
+        write_ics_line(out, &format!("SUMMARY:{}", ics_text(&summary)))?;
+        write_ics_line(out, &format!("DESCRIPTION:{}", ics_text(&description)))?;
+        write!(out, "END:VEVENT\r\n")?;
+    }
+    write!(out, "END:VCALENDAR\r\n")
+}
+
+// Write a content line, folding it onto continuation lines (which start with a space) so none is over the
+// limit. Folds never split a UTF-8 character
+fn write_ics_line(out: &mut impl Write, line: &str) -> io::Result<()> {
+    let mut start = 0;
+    let mut limit = ICS_LINE_OCTETS;
+    while line.len() - start > limit {
+        let mut end = start + limit;
+        while !line.is_char_boundary(end) {
+            end -= 1;
+        }
+        write!(out, "{}\r\n ", &line[start..end])?;
+        start = end;
+        // Continuation lines lose an octet to their leading space
+        limit = ICS_LINE_OCTETS - 1;
+    }
+    write!(out, "{}\r\n", &line[start..])
+}
+
+// The task's name, or its ID if it is no longer in the list
+fn task_name(tasks: &[Task], id: u32) -> String {
+    tasks.iter().find(|task| task.id == id).map_or_else(|| id.to_string(), |task| task.name.clone())
+}
+
+fn local_time(timestamp: i64) -> String {
+    DateTime::from_timestamp(timestamp, 0).unwrap_or_default()
+        .with_timezone(&chrono::Local)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
+
+fn utc_time(timestamp: i64) -> String {
+    DateTime::from_timestamp(timestamp, 0).unwrap_or_default().format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn minutes(seconds: u32) -> String {
+    format!("{}m{:02}s", seconds / 60, seconds % 60)
+}
+
+// Quote a field if it holds anything CSV treats specially
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn ics_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::SessionOutcome;
+
+    // A twenty minute session that ran a minute over, with work sessions spent on task 7
+    fn record(session: TimerSession, started_at: i64) -> SessionRecord {
+        SessionRecord {
+            planned_seconds: 1200,
+            actual_seconds: 1260,
+            paused_seconds: 60,
+            ended_at: started_at + 1260,
+            cycle: 1,
+            task: (session == TimerSession::Working).then_some(7),
+            ..SessionRecord::sample(session, SessionOutcome::Completed, started_at)
+        }
+    }
+
+    fn exported(records: &[SessionRecord], format: ExportFormat, session: Option<TimerSession>) -> String {
+        let tasks = [Task { id: 7, name: "Write report".to_string(), estimate: None, status: Default::default() }];
+        let mut out = Vec::new();
+        export(records, format, session, &tasks, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn exports_each_format() {
+        let records = [record(TimerSession::Working, 1_700_000_000), record(TimerSession::Resting, 1_700_001_260)];
+
+        let csv = exported(&records, ExportFormat::Csv, None);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].ends_with(",Working,Completed,1200,1260,60,2,Write report"));
+
+        let jsonl = exported(&records, ExportFormat::Jsonl, None);
+        let parsed: Vec<SessionRecord> = jsonl.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(parsed, records);
+
+        // Only the work session becomes an event
+        let ics = exported(&records, ExportFormat::Ics, None);
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\n") && ics.ends_with("END:VCALENDAR\r\n"));
+        assert_eq!(ics.matches("BEGIN:VEVENT").count(), 1);
+        assert!(ics.contains("DTSTART:20231114T221320Z\r\n"));
+        assert!(ics.contains("DTEND:20231114T223420Z\r\n"));
+        assert!(ics.contains("SUMMARY:Pomodoro: Write report\r\n"));
+        // Long lines are folded, and read back whole once unfolded
+        assert!(ics.split("\r\n").all(|line| line.len() <= ICS_LINE_OCTETS));
+        assert!(ics.replace("\r\n ", "").contains("DESCRIPTION:Completed Working session\\n20m00s planned\\, 21m00s actual\\, 1m00s paused\\nCycle 2\\nTask: Write report\r\n"));
+
+        // Unless another type of session is asked for
+        let ics = exported(&records, ExportFormat::Ics, Some(TimerSession::Resting));
+        assert_eq!(ics.matches("BEGIN:VEVENT").count(), 1);
+        assert!(ics.contains("SUMMARY:Pomodoro: Resting\r\n"));
+    }
+
+    #[test]
+    fn escapes_special_characters() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a \"b\", c"), "\"a \"\"b\"\", c\"");
+        assert_eq!(ics_text("a;b,c\\d\ne"), "a\\;b\\,c\\\\d\\ne");
+    }
+
+    #[test]
+    fn folds_long_lines_between_characters() {
+        let line = format!("SUMMARY:{}", "é".repeat(60));
+        let mut out = Vec::new();
+        write_ics_line(&mut out, &line).unwrap();
+        let folded = String::from_utf8(out).unwrap();
+        assert!(folded.split("\r\n").all(|part| part.len() <= ICS_LINE_OCTETS));
+        assert_eq!(folded.replace("\r\n ", ""), format!("{line}\r\n"));
+    }
+}
@@ -0,0 +1,197 @@
+/*
+History keeps a record of every session the timer has finished, skipped, abandoned or lost to a crash.
+Records are appended as JSON lines to history.jsonl in the project data directory. Each line carries the
+schema version it was written with, and fields are only ever added, so old files stay readable.
+*/
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local};
+use serde::{Serialize, Deserialize};
+
+use crate::config_manager::ConfigManager;
+use crate::timer::TimerSession;
+
+pub const SCHEMA_VERSION: u32 = 1;
+const HISTORY_FILE_NAME: &str = "history.jsonl";
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionOutcome {
+    // Ran down to zero
+    Completed,
+    // Moved past before it ran down
+    Skipped,
+    // The timer was stopped part way through
+    Aborted,
+    // Cut short by the app closing or the machine sleeping, and not carried on
+    Interrupted,
+}
+
+impl SessionOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Completed => "Completed",
+            Self::Skipped => "Skipped",
+            Self::Aborted => "Aborted",
+            Self::Interrupted => "Interrupted",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SessionRecord {
+    pub schema: u32,
+    pub session: TimerSession,
+    pub outcome: SessionOutcome,
+    pub planned_seconds: u32,
+    // Wall time from start to end, including any time spent paused
+    pub actual_seconds: u32,
+    pub paused_seconds: u32,
+    // Time added to the planned length by snoozing
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub extended_seconds: u32,
+    // Unix timestamps in seconds
+    pub started_at: i64,
+    pub ended_at: i64,
+    // Work-relief cycle the session belonged to, counting from 0
+    pub cycle: u32,
+    // Task the user was working on, for work sessions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task: Option<u32>,
+}
+
+fn is_zero(value: &u32) -> bool {
+    *value == 0
+}
+
+impl SessionRecord {
+    pub fn started_local(&self) -> DateTime<Local> {
+        DateTime::from_timestamp(self.started_at, 0).unwrap_or_default().with_timezone(&Local)
+    }
+
+    // A record for tests to build on, a minute planned with ten seconds of it spent paused
+    #[cfg(test)]
+    pub fn sample(session: TimerSession, outcome: SessionOutcome, started_at: i64) -> Self {
+        SessionRecord {
+            schema: SCHEMA_VERSION,
+            session,
+            outcome,
+            planned_seconds: 60,
+            actual_seconds: 70,
+            paused_seconds: 10,
+            extended_seconds: 0,
+            started_at,
+            ended_at: started_at + 70,
+            cycle: 0,
+            task: None,
+        }
+    }
+}
+
+// Narrows down a query. Every field left as None matches everything
+#[derive(Clone, Debug, Default)]
+pub struct HistoryFilter {
+    // Unix timestamps in seconds, compared against when the session started. `until` is exclusive
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    pub session: Option<TimerSession>,
+    pub outcome: Option<SessionOutcome>,
+}
+
+impl HistoryFilter {
+    pub fn matches(&self, record: &SessionRecord) -> bool {
+        self.since.is_none_or(|since| record.started_at >= since)
+            && self.until.is_none_or(|until| record.started_at < until)
+            && self.session.is_none_or(|session| record.session == session)
+            && self.outcome.is_none_or(|outcome| record.outcome == outcome)
+    }
+}
+
+pub struct History {
+    path: PathBuf,
+}
+
+impl History {
+    pub fn open_default() -> Result<Self, String> {
+        let proj_dirs = ConfigManager::get_project_dirs()
+            .ok_or("Could not determine correct data directory")?;
+        let data_dir = proj_dirs.data_dir();
+        fs::create_dir_all(data_dir).map_err(|e| format!("Could not create {}: {e}", data_dir.display()))?;
+        Ok(Self::at(data_dir.join(HISTORY_FILE_NAME)))
+    }
+
+    pub fn at(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn append(&self, record: &SessionRecord) -> io::Result<()> {
+        let mut line = serde_json::to_string(record).map_err(io::Error::other)?;
+        line.push('\n');
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        // One write per record, so concurrent appenders never interleave within a line
+        file.write_all(line.as_bytes())
+    }
+
+    // Every readable record, oldest first. Lines that can't be parsed (e.g. cut short by a crash) are skipped
+    pub fn records(&self) -> io::Result<Vec<SessionRecord>> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut records = Vec::new();
+        for line in BufReader::new(file).lines() {
+            if let Ok(record) = serde_json::from_str::<SessionRecord>(&line?) {
+                records.push(record);
+            }
+        }
+        Ok(records)
+    }
+
+    pub fn query(&self, filter: &HistoryFilter) -> io::Result<Vec<SessionRecord>> {
+        let mut records = self.records()?;
+        records.retain(|record| filter.matches(record));
+        Ok(records)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn appends_and_queries_records() {
+        let path = std::env::temp_dir().join(format!("rustypomodoro-history-test-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let history = History::at(&path);
+        assert_eq!(history.records().unwrap(), Vec::new());
+
+        let work = SessionRecord::sample(TimerSession::Working, SessionOutcome::Completed, 1_000);
+        let rest = SessionRecord::sample(TimerSession::Resting, SessionOutcome::Completed, 2_000);
+        let aborted = SessionRecord::sample(TimerSession::Working, SessionOutcome::Aborted, 3_000);
+        for r in [&work, &rest, &aborted] {
+            history.append(r).unwrap();
+        }
+        // A torn final line from a crash must not hide the rest
+        fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"{\"schema\":1,\"sess").unwrap();
+
+        assert_eq!(history.records().unwrap(), vec![work.clone(), rest.clone(), aborted.clone()]);
+        let working = HistoryFilter { session: Some(TimerSession::Working), ..Default::default() };
+        assert_eq!(history.query(&working).unwrap(), vec![work.clone(), aborted.clone()]);
+        let window = HistoryFilter { since: Some(1_500), until: Some(3_000), ..Default::default() };
+        assert_eq!(history.query(&window).unwrap(), vec![rest]);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn schema_is_stable() {
+        let line = serde_json::to_string(&SessionRecord::sample(TimerSession::Break, SessionOutcome::Skipped, 5)).unwrap();
+        assert_eq!(line, r#"{"schema":1,"session":"break","outcome":"skipped","planned_seconds":60,"actual_seconds":70,"paused_seconds":10,"started_at":5,"ended_at":75,"cycle":0}"#);
+    }
+}
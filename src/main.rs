@@ -1,23 +1,49 @@
+mod alert;
 mod app;
 mod cli;
+mod clock;
+mod commands;
+mod config_format;
+mod config_layers;
 mod config_manager;
+mod config_watcher;
+mod daemon;
+mod export;
+mod history;
+#[cfg(feature = "notifications")]
+mod notify;
+mod report;
+mod snapshot;
+mod status_bar;
+mod tasks;
 mod timer;
 mod utils;
-use std::env;
+use std::process::ExitCode;
+
+use clap::Parser;
 
 use crate::app::PomodoroApp;
 
-fn main() -> Result<(), String> {
+fn main() -> ExitCode {
     // Just parse arguments here if any (For later GUI implement)
     // Then launch CLI or GUI
-    let args: Vec<String> = env::args().collect();
-    
-    if args.len() != 1 {
-        println!("Incorrect usage! Please run without any arguments.");
-    } else {
-        let mut app = PomodoroApp::new();
-        app.init();
-        cli::run(&mut app);
+    let args = commands::Cli::parse();
+
+    let mut app = PomodoroApp::new();
+    app.init(args.config, args.set);
+    match args.command {
+        Some(command) => {
+            if let Some(e) = app.config_error() {
+                eprintln!("Warning: {e}");
+            }
+            if let Some(e) = app.env_error() {
+                eprintln!("Warning: {e}");
+            }
+            commands::run(&mut app, command)
+        }
+        None => {
+            cli::run(&mut app);
+            ExitCode::SUCCESS
+        }
     }
-    Ok(())
 }
@@ -0,0 +1,277 @@
+/*
+Desktop notifications through the freedesktop notification service on the D-Bus session bus.
+Session changes are announced as notifications, and the buttons on those notifications are fed back
+to the running timer as commands.
+*/
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use zbus::blocking::Connection;
+use zbus::zvariant::Value;
+
+use crate::timer::{TimerCommand, TimerEvent, TimerSession, TimerState};
+
+const APP_NAME: &str = "Rusty Pomodoro";
+// Let the notification server pick its default timeout
+const EXPIRE_DEFAULT: i32 = -1;
+
+const ACTION_NEXT: &str = "next";
+const ACTION_STOP: &str = "stop";
+const ACTION_SNOOZE: &str = "snooze";
+
+// Where actions get sent, and how long a snooze is
+type ActionTarget = (Sender<TimerCommand>, Duration);
+
+#[zbus::proxy(
+    interface = "org.freedesktop.Notifications",
+    default_service = "org.freedesktop.Notifications",
+    default_path = "/org/freedesktop/Notifications",
+    gen_async = false
+)]
+trait Notifications {
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &self,
+        app_name: &str,
+        replaces_id: u32,
+        app_icon: &str,
+        summary: &str,
+        body: &str,
+        actions: &[&str],
+        hints: HashMap<&str, Value<'_>>,
+        expire_timeout: i32,
+    ) -> zbus::Result<u32>;
+
+    #[zbus(signal)]
+    fn action_invoked(&self, id: u32, action_key: &str) -> zbus::Result<()>;
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Notification {
+    pub summary: String,
+    pub body: String,
+    // Pairs of action key and button label
+    pub actions: Vec<(&'static str, &'static str)>,
+}
+
+pub struct Notifier {
+    proxy: NotificationsProxy<'static>,
+    // Id of the notification on screen, so each new one replaces the last instead of piling up
+    current_id: Arc<AtomicU32>,
+    // Swapped out each time a new timer is started
+    cmd_tx: Arc<Mutex<Option<ActionTarget>>>,
+    last_seen: Option<(TimerState, TimerSession)>,
+}
+
+impl Notifier {
+    pub fn connect() -> zbus::Result<Self> {
+        Self::with_connection(&Connection::session()?)
+    }
+
+    pub fn with_connection(connection: &Connection) -> zbus::Result<Self> {
+        let proxy = NotificationsProxy::new(connection)?;
+        let current_id = Arc::new(AtomicU32::new(0));
+        let cmd_tx: Arc<Mutex<Option<ActionTarget>>> = Arc::new(Mutex::new(None));
+
+        // Listen for button presses for as long as the connection lives
+        let actions = proxy.receive_action_invoked()?;
+        let (listen_id, listen_tx) = (Arc::clone(&current_id), Arc::clone(&cmd_tx));
+        thread::spawn(move || {
+            for signal in actions {
+                let Ok(args) = signal.args() else { continue };
+                if args.id != listen_id.load(Ordering::SeqCst) {
+                    continue;
+                }
+                if let Some((tx, snooze)) = listen_tx.lock().unwrap().as_ref()
+                    && let Some(cmd) = command_for_action(args.action_key, *snooze) {
+                    let _ = tx.send(cmd);
+                }
+            }
+        });
+
+        Ok(Self { proxy, current_id, cmd_tx, last_seen: None })
+    }
+
+    // Route notification actions to a newly started timer
+    pub fn attach(&mut self, cmd_tx: Sender<TimerCommand>, snooze: Duration) {
+        *self.cmd_tx.lock().unwrap() = Some((cmd_tx, snooze));
+        self.last_seen = None;
+    }
+
+    // Best-effort: a missing notification server never interrupts the timer
+    pub fn handle_event(&mut self, event: &TimerEvent) {
+        let notification = notification_for(self.last_seen, event);
+        self.last_seen = Some((event.state, event.session));
+
+        if let Some(notification) = notification {
+            let _ = self.show(&notification);
+        }
+    }
+
+    fn show(&self, notification: &Notification) -> zbus::Result<()> {
+        let actions: Vec<&str> = notification.actions.iter()
+            .flat_map(|(key, label)| [*key, *label])
+            .collect();
+        let id = self.proxy.notify(
+            APP_NAME,
+            self.current_id.load(Ordering::SeqCst),
+            "",
+            &notification.summary,
+            &notification.body,
+            &actions,
+            HashMap::new(),
+            EXPIRE_DEFAULT,
+        )?;
+        self.current_id.store(id, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+// Decide what, if anything, to announce when the timer moves from `previous` to `event`
+pub fn notification_for(previous: Option<(TimerState, TimerSession)>, event: &TimerEvent) -> Option<Notification> {
+    // The user just started the timer themselves, no need to tell them
+    let (previous_state, previous_session) = previous?;
+
+    match event.state {
+        TimerState::Waiting if !matches!(previous_state, TimerState::Waiting) => Some(Notification {
+            summary: format!("{} session complete", event.session.as_str()),
+            body: match event.auto_advance_in {
+                Some(grace) => format!("The next session starts in {grace} seconds"),
+                None => "Waiting for you to start the next session".to_string(),
+            },
+            actions: if event.snoozes_left > 0 {
+                vec![(ACTION_NEXT, "Start next session"), (ACTION_SNOOZE, "Snooze"), (ACTION_STOP, "Stop")]
+            } else {
+                vec![(ACTION_NEXT, "Start next session"), (ACTION_STOP, "Stop")]
+            },
+        }),
+        TimerState::CountDown if previous_session != event.session => Some(Notification {
+            summary: format!("{} session started", event.session.as_str()),
+            body: format!("{:02}:{:02} remaining", event.remaining / 60, event.remaining % 60),
+            actions: Vec::new(),
+        }),
+        _ => None,
+    }
+}
+
+pub fn command_for_action(action_key: &str, snooze: Duration) -> Option<TimerCommand> {
+    match action_key {
+        ACTION_NEXT => Some(TimerCommand::Next),
+        ACTION_SNOOZE => Some(TimerCommand::Extend(snooze)),
+        ACTION_STOP => Some(TimerCommand::Stop),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixStream;
+    use std::sync::mpsc::{self, Receiver};
+    use std::time::Duration;
+    use zbus::blocking::connection;
+    use zbus::zvariant::OwnedValue;
+
+    const PATH: &str = "/org/freedesktop/Notifications";
+    const INTERFACE: &str = "org.freedesktop.Notifications";
+
+    // Replaced id, summary and actions of a Notify call
+    type Shown = (u32, String, Vec<String>);
+
+    // Stand-in for the desktop's notification server, recording every Notify call
+    struct FakeNotificationServer {
+        shown: Mutex<Sender<Shown>>,
+        next_id: AtomicU32,
+    }
+
+    #[zbus::interface(name = "org.freedesktop.Notifications")]
+    impl FakeNotificationServer {
+        #[allow(clippy::too_many_arguments)]
+        fn notify(
+            &self,
+            _app_name: String,
+            replaces_id: u32,
+            _app_icon: String,
+            summary: String,
+            _body: String,
+            actions: Vec<String>,
+            _hints: HashMap<String, OwnedValue>,
+            _expire_timeout: i32,
+        ) -> u32 {
+            let id = if replaces_id == 0 { self.next_id.fetch_add(1, Ordering::SeqCst) } else { replaces_id };
+            let _ = self.shown.lock().unwrap().send((replaces_id, summary, actions));
+            id
+        }
+    }
+
+    // Peer-to-peer connection pair, so no real session bus is needed
+    fn connect_to_fake() -> (Connection, Connection, Receiver<Shown>) {
+        let (server_stream, client_stream) = UnixStream::pair().unwrap();
+        let (shown_tx, shown_rx) = mpsc::channel();
+        let fake = FakeNotificationServer { shown: Mutex::new(shown_tx), next_id: AtomicU32::new(7) };
+
+        let server = thread::spawn(move || {
+            connection::Builder::async_io_unix_stream(server_stream)
+                .server(zbus::Guid::generate()).unwrap()
+                .p2p()
+                .serve_at(PATH, fake).unwrap()
+                .build().unwrap()
+        });
+        let client = connection::Builder::async_io_unix_stream(client_stream).p2p().build().unwrap();
+        (server.join().unwrap(), client, shown_rx)
+    }
+
+    fn event(state: TimerState, session: TimerSession, remaining: u32) -> TimerEvent {
+        TimerEvent { state, session, segment: String::new(), remaining, session_length: remaining, cycles_complete: 0, auto_advance_in: None, snoozes_left: 0, gap: None }
+    }
+
+    #[test]
+    fn notifies_on_session_changes_only() {
+        use TimerSession::*;
+        use TimerState::*;
+
+        assert_eq!(notification_for(None, &event(CountDown, Working, 60)), None);
+        assert_eq!(notification_for(Some((CountDown, Working)), &event(CountDown, Working, 59)), None);
+        assert_eq!(notification_for(Some((Waiting, Working)), &event(Waiting, Working, 0)), None);
+        assert_eq!(notification_for(Some((Paused, Working)), &event(CountDown, Working, 59)), None);
+
+        let ended = notification_for(Some((CountDown, Working)), &event(Waiting, Working, 0)).unwrap();
+        assert_eq!(ended.summary, "Working session complete");
+        assert_eq!(ended.actions.len(), 2);
+        let snoozable = TimerEvent { snoozes_left: 1, ..event(Waiting, Working, 0) };
+        assert_eq!(notification_for(Some((CountDown, Working)), &snoozable).unwrap().actions[1], (ACTION_SNOOZE, "Snooze"));
+
+        let started = notification_for(Some((Waiting, Working)), &event(CountDown, Resting, 20)).unwrap();
+        assert_eq!(started.summary, "Resting session started");
+        assert_eq!(started.body, "00:20 remaining");
+    }
+
+    #[test]
+    fn sends_notify_and_maps_actions_to_commands() {
+        let (server, client, shown_rx) = connect_to_fake();
+        let mut notifier = Notifier::with_connection(&client).unwrap();
+        let (cmd_tx, cmd_rx) = mpsc::channel();
+        notifier.attach(cmd_tx, Duration::from_secs(300));
+
+        notifier.handle_event(&event(TimerState::CountDown, TimerSession::Working, 1));
+        notifier.handle_event(&event(TimerState::Waiting, TimerSession::Working, 0));
+        let (replaces, summary, actions) = shown_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!((replaces, summary.as_str()), (0, "Working session complete"));
+        assert_eq!(actions, ["next", "Start next session", "stop", "Stop"]);
+
+        // Actions on stale notifications are ignored, the one on screen is honoured
+        server.emit_signal(None::<()>, PATH, INTERFACE, "ActionInvoked", &(3u32, "next")).unwrap();
+        server.emit_signal(None::<()>, PATH, INTERFACE, "ActionInvoked", &(7u32, "next")).unwrap();
+        assert!(matches!(cmd_rx.recv_timeout(Duration::from_secs(5)), Ok(TimerCommand::Next)));
+        assert!(cmd_rx.recv_timeout(Duration::from_millis(100)).is_err());
+
+        // The next notification replaces the previous one
+        notifier.handle_event(&event(TimerState::CountDown, TimerSession::Resting, 20));
+        let (replaces, summary, _) = shown_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!((replaces, summary.as_str()), (7, "Resting session started"));
+    }
+}
@@ -0,0 +1,216 @@
+/*
+Reports summarise the session history over a range of days: how much focused work got done, and how well
+the eye relief and break sessions were kept up.
+*/
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use chrono::{Days, Local, NaiveDate};
+
+use crate::utils::get_display_time;
+use crate::history::{History, HistoryFilter, SessionOutcome, SessionRecord};
+use crate::timer::TimerSession;
+use crate::utils::local_midnight;
+
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+const BAR_WIDTH: u32 = 30;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DaySummary {
+    pub focused_seconds: u32,
+    pub completed_work: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    // Every day in the range, including the empty ones
+    pub days: BTreeMap<NaiveDate, DaySummary>,
+    pub focused_seconds: u32,
+    pub completed_work: u32,
+    pub breaks_taken: u32,
+    pub breaks_skipped: u32,
+    pub reliefs_taken: u32,
+    pub reliefs_skipped: u32,
+    // Most consecutive days with at least one completed work session
+    pub longest_streak: u32,
+}
+
+impl Report {
+    // Share of completed work sessions that were followed by resting the eyes, relief or break, as the
+    // 20-20-20 rule asks. None if there was no work to rest from
+    pub fn relief_compliance(&self) -> Option<u32> {
+        if self.completed_work == 0 {
+            return None;
+        }
+        let rested = self.reliefs_taken + self.breaks_taken;
+        Some((rested * 100 / self.completed_work).min(100))
+    }
+}
+
+// History filter covering whole local days from `from` to `to` inclusive
+pub fn filter_for(from: NaiveDate, to: NaiveDate) -> HistoryFilter {
+    HistoryFilter {
+        since: local_midnight(from),
+        until: to.checked_add_days(Days::new(1)).and_then(local_midnight),
+        ..Default::default()
+    }
+}
+
+// Report on the recorded history between two dates, inclusive
+pub fn load(from: NaiveDate, to: NaiveDate) -> Result<Report, String> {
+    let history = History::open_default()?;
+    let records = history.query(&filter_for(from, to))
+        .map_err(|e| format!("Could not read {}: {e}", history.path().display()))?;
+    Ok(build(&records, from, to))
+}
+
+// The last `days` days, ending today
+pub fn last_days(days: u64) -> (NaiveDate, NaiveDate) {
+    let today = Local::now().date_naive();
+    (today.checked_sub_days(Days::new(days.saturating_sub(1))).unwrap_or(today), today)
+}
+
+pub fn build(records: &[SessionRecord], from: NaiveDate, to: NaiveDate) -> Report {
+    let days: BTreeMap<NaiveDate, DaySummary> = from.iter_days()
+        .take_while(|day| *day <= to)
+        .map(|day| (day, DaySummary::default()))
+        .collect();
+    let mut report = Report {
+        from,
+        to,
+        days,
+        focused_seconds: 0,
+        completed_work: 0,
+        breaks_taken: 0,
+        breaks_skipped: 0,
+        reliefs_taken: 0,
+        reliefs_skipped: 0,
+        longest_streak: 0,
+    };
+
+    for record in records {
+        let Some(day) = report.days.get_mut(&record.started_local().date_naive()) else { continue };
+        let completed = record.outcome == SessionOutcome::Completed;
+        match record.session {
+            TimerSession::Working => {
+                let focused = record.actual_seconds.saturating_sub(record.paused_seconds);
+                day.focused_seconds += focused;
+                report.focused_seconds += focused;
+                if completed {
+                    day.completed_work += 1;
+                    report.completed_work += 1;
+                }
+            }
+            TimerSession::Resting if completed => report.reliefs_taken += 1,
+            TimerSession::Resting => report.reliefs_skipped += 1,
+            TimerSession::Break if completed => report.breaks_taken += 1,
+            TimerSession::Break => report.breaks_skipped += 1,
+        }
+    }
+
+    let mut streak = 0;
+    for day in report.days.values() {
+        streak = if day.completed_work > 0 { streak + 1 } else { 0 };
+        report.longest_streak = report.longest_streak.max(streak);
+    }
+    report
+}
+
+pub fn render(report: &Report) -> String {
+    let mut out = String::new();
+    let compliance = report.relief_compliance().map_or("n/a".to_string(), |c| format!("{c}%"));
+
+    let _ = writeln!(out, "REPORT {} to {}\n", report.from, report.to);
+    let _ = writeln!(out, "Focused time:        {}", format_hours(report.focused_seconds));
+    let _ = writeln!(out, "Work sessions:       {}", report.completed_work);
+    let _ = writeln!(out, "Breaks taken:        {} ({} skipped)", report.breaks_taken, report.breaks_skipped);
+    let _ = writeln!(out, "Eye reliefs taken:   {} ({} skipped)", report.reliefs_taken, report.reliefs_skipped);
+    let _ = writeln!(out, "20-20-20 compliance: {compliance}");
+    let _ = writeln!(out, "Longest streak:      {} day{}", report.longest_streak, if report.longest_streak == 1 {""} else {"s"});
+    let _ = writeln!(out, "\nTrend: {}\n", sparkline(report));
+
+    let max = report.days.values().map(|d| d.focused_seconds).max().unwrap_or(0);
+    let _ = writeln!(out, "{:<10}  {:>7}  {:>5}", "Day", "Focused", "Work");
+    for (date, day) in &report.days {
+        let bar_len = (day.focused_seconds * BAR_WIDTH).checked_div(max).unwrap_or(0);
+        let row = format!("{:<10}  {:>7}  {:>5}  {}",
+            date.format("%a %d %b"), format_hours(day.focused_seconds), day.completed_work, "█".repeat(bar_len as usize)
+        );
+        let _ = writeln!(out, "{}", row.trim_end());
+    }
+    out
+}
+
+// One character per day, scaled to the busiest day
+fn sparkline(report: &Report) -> String {
+    let max = report.days.values().map(|d| d.focused_seconds).max().unwrap_or(0);
+    report.days.values()
+        .map(|day| {
+            if max == 0 || day.focused_seconds == 0 {
+                ' '
+            } else {
+                SPARKS[((day.focused_seconds * (SPARKS.len() as u32 - 1)) / max) as usize]
+            }
+        })
+        .collect()
+}
+
+// h:mm once there's an hour or more, mm:ss below that
+fn format_hours(seconds: u32) -> String {
+    if seconds >= 60 * 60 {
+        format!("{}h{:02}m", seconds / 3600, (seconds % 3600) / 60)
+    } else {
+        get_display_time(seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    // Noon on the given day, never paused
+    fn record(day: NaiveDate, session: TimerSession, outcome: SessionOutcome, actual_seconds: u32) -> SessionRecord {
+        let started_at = Local.from_local_datetime(&day.and_hms_opt(12, 0, 0).unwrap()).unwrap().timestamp();
+        SessionRecord {
+            planned_seconds: actual_seconds,
+            actual_seconds,
+            paused_seconds: 0,
+            ended_at: started_at + actual_seconds as i64,
+            ..SessionRecord::sample(session, outcome, started_at)
+        }
+    }
+
+    #[test]
+    fn summarises_sessions_per_day() {
+        use SessionOutcome::*;
+        use TimerSession::*;
+
+        let day = |d| NaiveDate::from_ymd_opt(2026, 3, d).unwrap();
+        let records = vec![
+            record(day(1), Working, Completed, 1200),
+            record(day(1), Resting, Completed, 20),
+            record(day(1), Working, Completed, 1200),
+            record(day(1), Break, Skipped, 10),
+            record(day(2), Working, Completed, 1200),
+            record(day(2), Resting, Aborted, 5),
+            record(day(4), Working, Aborted, 600),
+            // Outside the range
+            record(day(9), Working, Completed, 1200),
+        ];
+        let report = build(&records, day(1), day(4));
+
+        assert_eq!(report.days.len(), 4);
+        assert_eq!(report.days[&day(1)], DaySummary { focused_seconds: 2400, completed_work: 2 });
+        assert_eq!(report.days[&day(4)], DaySummary { focused_seconds: 600, completed_work: 0 });
+        assert_eq!(report.focused_seconds, 4200);
+        assert_eq!(report.completed_work, 3);
+        assert_eq!((report.breaks_taken, report.breaks_skipped), (0, 1));
+        assert_eq!((report.reliefs_taken, report.reliefs_skipped), (1, 1));
+        assert_eq!(report.relief_compliance(), Some(33));
+        assert_eq!(report.longest_streak, 2);
+        assert_eq!(sparkline(&report), "█▄ ▂");
+    }
+}
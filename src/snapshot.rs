@@ -0,0 +1,189 @@
+/*
+Snapshots let a session outlive the process running it. While a timer runs, where it is gets written to
+timer.json in the project data directory every few seconds, and the file is removed once the timer stops.
+A snapshot still there at launch means the last run ended without stopping its timer (a crash, a kill, the
+machine losing power), so the session can be picked up where it was, thrown away, or logged to history.
+*/
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use serde::{Serialize, Deserialize};
+
+use crate::config_manager::{ConfigManager, Settings};
+use crate::history::{SessionOutcome, SessionRecord, SCHEMA_VERSION};
+use crate::timer::{TimerSession, TimerState};
+use crate::utils::format_duration;
+
+const SNAPSHOT_FILE_NAME: &str = "timer.json";
+// Held by whichever instance is running a timer, so its snapshot isn't taken for an interrupted one
+const LOCK_FILE_NAME: &str = "timer.lock";
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TimerSnapshot {
+    // The timer's own settings, which may be one-off ones rather than the saved ones
+    pub settings: Settings,
+    pub segment_index: usize,
+    pub state: TimerState,
+    pub session: TimerSession,
+    pub segment: String,
+    // Seconds left in the session, and its planned length
+    pub remaining: u32,
+    pub session_length: u32,
+    pub cycles_complete: u32,
+    // Snoozes taken this session, and the seconds they added to `session_length`
+    #[serde(default)]
+    pub snoozes: u32,
+    #[serde(default)]
+    pub extended_seconds: u32,
+    // Set while Waiting on a session that has run down but not gone into history, as a snooze could carry it on
+    #[serde(default)]
+    pub pending_record: bool,
+    // Unix timestamps in seconds. `saved_at` is the last moment the session is known to have been running
+    pub started_at: i64,
+    pub saved_at: i64,
+    // Time spent paused up to `saved_at`
+    pub paused_seconds: u32,
+    // Task the user was working on, picked up again on resume
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task: Option<u32>,
+}
+
+impl TimerSnapshot {
+    // One line saying where the session was, e.g. "Work (Working), 12m30s of 25m left, Paused"
+    pub fn describe(&self) -> String {
+        format!("{} ({}), {} of {} left, {}", self.segment, self.session.as_str(), format_duration(self.remaining),
+            format_duration(self.session_length), self.state.as_str())
+    }
+
+    // Whether the session has yet to go into history
+    pub fn is_unrecorded(&self) -> bool {
+        matches!(self.state, TimerState::CountDown | TimerState::Paused) || self.pending_record
+    }
+
+    // The session as it stood when the snapshot was taken, for the history log. One that had run down is
+    // complete, and ended when it ran down. The file may have been edited by hand, so nothing here may overflow
+    pub fn session_record(&self) -> SessionRecord {
+        let (outcome, actual_seconds) = match self.state {
+            TimerState::Waiting => (SessionOutcome::Completed, self.session_length.saturating_add(self.paused_seconds)),
+            _ => (SessionOutcome::Interrupted, self.saved_at.saturating_sub(self.started_at).clamp(0, u32::MAX.into()) as u32),
+        };
+        SessionRecord {
+            schema: SCHEMA_VERSION,
+            session: self.session,
+            outcome,
+            planned_seconds: self.session_length.saturating_sub(self.extended_seconds),
+            actual_seconds,
+            paused_seconds: self.paused_seconds,
+            extended_seconds: self.extended_seconds,
+            started_at: self.started_at,
+            ended_at: self.started_at.saturating_add(actual_seconds.into()),
+            cycle: self.cycles_complete,
+            // Only work sessions are recorded against a task
+            task: self.task.filter(|_| self.session == TimerSession::Working),
+        }
+    }
+}
+
+pub struct Snapshots {
+    path: PathBuf,
+    // Taken with the first save and let go when the snapshot is cleared
+    lock: Option<File>,
+}
+
+impl Snapshots {
+    pub fn open_default() -> Result<Self, String> {
+        let proj_dirs = ConfigManager::get_project_dirs()
+            .ok_or("Could not determine correct data directory")?;
+        let data_dir = proj_dirs.data_dir();
+        fs::create_dir_all(data_dir).map_err(|e| format!("Could not create {}: {e}", data_dir.display()))?;
+        Ok(Self::at(data_dir.join(SNAPSHOT_FILE_NAME)))
+    }
+
+    pub fn at(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into(), lock: None }
+    }
+
+    // The snapshot left behind by a timer that was never stopped. None if there isn't one, it can't be read,
+    // or its timer is still running in another instance
+    pub fn interrupted(&self) -> Option<TimerSnapshot> {
+        let _lock = self.try_lock()?;
+        let contents = fs::read_to_string(&self.path).ok()?;
+        serde_json::from_str(&contents).ok()
+    }
+
+    // Replace the snapshot, through a temporary file so a crash part way leaves the last one intact
+    pub fn save(&mut self, snapshot: &TimerSnapshot) -> io::Result<()> {
+        if self.lock.is_none() {
+            let lock = self.try_lock().ok_or_else(|| io::Error::other("Another instance is running a timer"))?;
+            self.lock = Some(lock);
+        }
+        let contents = serde_json::to_string_pretty(snapshot).map_err(io::Error::other)?;
+        let temp_path = self.path.with_extension(format!("json.tmp-{}", std::process::id()));
+        let mut file = File::create(&temp_path)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temp_path, &self.path)
+    }
+
+    pub fn clear(&mut self) {
+        let _ = fs::remove_file(&self.path);
+        self.lock = None;
+    }
+
+    fn try_lock(&self) -> Option<File> {
+        let lock_path = self.path.with_file_name(LOCK_FILE_NAME);
+        let file = OpenOptions::new().create(true).truncate(false).write(true).open(lock_path).ok()?;
+        file.try_lock().ok()?;
+        Some(file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshots_are_only_interrupted_once_their_owner_lets_go() {
+        let dir = std::env::temp_dir().join(format!("rustypomodoro-snapshot-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let snapshot = TimerSnapshot {
+            settings: Settings::default(),
+            segment_index: 0,
+            state: TimerState::Paused,
+            session: TimerSession::Working,
+            segment: "Work".to_string(),
+            remaining: 600,
+            session_length: 1800,
+            cycles_complete: 0,
+            snoozes: 1,
+            extended_seconds: 300,
+            pending_record: false,
+            started_at: 1_000,
+            saved_at: 2_000,
+            paused_seconds: 100,
+            task: Some(3),
+        };
+
+        let mut owner = Snapshots::at(dir.join(SNAPSHOT_FILE_NAME));
+        let other = Snapshots::at(dir.join(SNAPSHOT_FILE_NAME));
+        assert_eq!(other.interrupted(), None);
+        owner.save(&snapshot).unwrap();
+        assert_eq!(other.interrupted(), None);
+
+        // The owner going away without clearing it is what a crash looks like
+        drop(owner);
+        let found = other.interrupted().unwrap();
+        assert_eq!(found, snapshot);
+        assert_eq!(found.describe(), "Work (Working), 10m of 30m left, Paused");
+        let record = found.session_record();
+        assert_eq!((record.outcome, record.actual_seconds, record.ended_at), (SessionOutcome::Interrupted, 1_000, 2_000));
+        assert_eq!((record.planned_seconds, record.extended_seconds), (1500, 300));
+
+        // Nonsense from a hand-edited file gives a nonsense record, not a panic
+        let edited = TimerSnapshot { extended_seconds: 5_000, saved_at: i64::MAX, started_at: i64::MIN, ..snapshot };
+        let record = edited.session_record();
+        assert_eq!((record.planned_seconds, record.actual_seconds), (0, u32::MAX));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
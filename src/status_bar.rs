@@ -0,0 +1,162 @@
+/*
+Renders the daemon's timer status for status bars and tmux, in the same terms display_timer uses.
+Each format produces one line per status, so the same output works as a one-shot query or a stream.
+*/
+use clap::ValueEnum;
+use serde_json::json;
+
+use crate::utils::get_display_time;
+use crate::daemon::TimerStatus;
+use crate::timer::{TimerSession, TimerState};
+
+pub const DEFAULT_TEMPLATE: &str = "[{state}] {session} {remaining} (cycle {cycle}/{total_cycles})";
+const IDLE_TEXT: &str = "Idle";
+
+// Header that starts an i3bar protocol stream, followed by the opening of its infinite array
+pub const I3BAR_HEADER: &str = "{\"version\":1}\n[";
+
+#[derive(Copy, Clone, Debug, PartialEq, ValueEnum)]
+pub enum StatusFormat {
+    /// Text from the template
+    Plain,
+    /// JSON for a waybar custom module (text, tooltip, class, percentage)
+    Waybar,
+    /// Blocks for the i3bar protocol, also read by swaybar
+    I3bar,
+    /// Template text with tmux style codes and # escaped
+    Tmux,
+}
+
+// Fill in a template. Available placeholders: {state} {session} {segment} {remaining} {cycle} {total_cycles} {percentage}
+pub fn fill_template(template: &str, status: &TimerStatus) -> String {
+    template
+        .replace("{state}", status.state.as_str())
+        .replace("{session}", status.session.as_str())
+        .replace("{segment}", &status.segment)
+        .replace("{remaining}", &get_display_time(status.remaining))
+        .replace("{cycle}", &(status.cycles_complete + 1).to_string())
+        .replace("{total_cycles}", &status.total_cycles.to_string())
+        .replace("{percentage}", &percentage(status).to_string())
+}
+
+pub fn render(status: &TimerStatus, format: StatusFormat, template: &str) -> String {
+    let text = if status.state == TimerState::Idle {
+        IDLE_TEXT.to_string()
+    } else {
+        fill_template(template, status)
+    };
+
+    match format {
+        StatusFormat::Plain => text,
+        StatusFormat::Waybar => json!({
+            "text": text,
+            "tooltip": tooltip(status),
+            "class": class(status),
+            "percentage": percentage(status),
+        }).to_string(),
+        StatusFormat::I3bar => {
+            let block = json!({
+                "name": "rustypomodoro",
+                "full_text": text,
+                "short_text": get_display_time(status.remaining),
+                "color": colour(status),
+            });
+            // Every status line after the header is an array of blocks, separated by commas
+            format!("[{block}],")
+        }
+        StatusFormat::Tmux => {
+            // tmux expands #-sequences in the status line, and a newline would break it
+            let safe = text.replace('#', "##").replace(['\n', '\r'], " ");
+            format!("#[fg={}]{safe}#[default]", colour(status))
+        }
+    }
+}
+
+fn tooltip(status: &TimerStatus) -> String {
+    if status.state == TimerState::Idle {
+        return "No timer running".to_string();
+    }
+    let mut tooltip = format!("{}: {} left of {}\nCycle {}/{}",
+        status.session.as_str(), get_display_time(status.remaining), get_display_time(status.session_length),
+        status.cycles_complete + 1, status.total_cycles
+    );
+    if let Some(grace) = status.auto_advance_in {
+        tooltip.push_str(&format!("\nNext session starts in {grace}s"));
+    }
+    tooltip
+}
+
+// How far through the current session we are
+fn percentage(status: &TimerStatus) -> u32 {
+    if status.session_length == 0 {
+        return 0;
+    }
+    let elapsed = status.session_length.saturating_sub(status.remaining);
+    elapsed * 100 / status.session_length
+}
+
+// CSS class for waybar, so themes can style each state
+fn class(status: &TimerStatus) -> &'static str {
+    match (status.state, status.session) {
+        (TimerState::Idle, _) => "idle",
+        (TimerState::Paused, _) => "paused",
+        (TimerState::Waiting, _) => "waiting",
+        (TimerState::CountDown, TimerSession::Working) => "working",
+        (TimerState::CountDown, TimerSession::Resting) => "resting",
+        (TimerState::CountDown, TimerSession::Break) => "break",
+    }
+}
+
+fn colour(status: &TimerStatus) -> &'static str {
+    match class(status) {
+        "working" => "#e06c75",
+        "resting" | "break" => "#98c379",
+        "waiting" => "#e5c07b",
+        _ => "#abb2bf",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(state: TimerState) -> TimerStatus {
+        TimerStatus {
+            state,
+            session: TimerSession::Working,
+            segment: "Work".to_string(),
+            remaining: 15 * 60,
+            session_length: 20 * 60,
+            cycles_complete: 1,
+            total_cycles: 2,
+            auto_advance_in: None,
+        }
+    }
+
+    #[test]
+    fn renders_each_format() {
+        let counting = status(TimerState::CountDown);
+        assert_eq!(render(&counting, StatusFormat::Plain, DEFAULT_TEMPLATE), "[Count Down] Working 15:00 (cycle 2/2)");
+        assert_eq!(render(&counting, StatusFormat::Plain, "{session} {percentage}%"), "Working 25%");
+        assert_eq!(render(&counting, StatusFormat::Plain, "{segment} {cycle}/{total_cycles}"), "Work 2/2");
+
+        let waybar: serde_json::Value = serde_json::from_str(&render(&counting, StatusFormat::Waybar, "{remaining}")).unwrap();
+        assert_eq!(waybar["text"], "15:00");
+        assert_eq!(waybar["class"], "working");
+        assert_eq!(waybar["percentage"], 25);
+
+        let i3bar = render(&counting, StatusFormat::I3bar, "{remaining}");
+        let blocks: serde_json::Value = serde_json::from_str(i3bar.trim_end_matches(',')).unwrap();
+        assert_eq!(blocks[0]["full_text"], "15:00");
+
+        assert_eq!(render(&counting, StatusFormat::Tmux, "#{cycle}"), "#[fg=#e06c75]##2#[default]");
+    }
+
+    #[test]
+    fn idle_renders_without_session_details() {
+        let idle = status(TimerState::Idle);
+        assert_eq!(render(&idle, StatusFormat::Plain, DEFAULT_TEMPLATE), "Idle");
+        let waybar: serde_json::Value = serde_json::from_str(&render(&idle, StatusFormat::Waybar, DEFAULT_TEMPLATE)).unwrap();
+        assert_eq!(waybar["class"], "idle");
+    }
+}
@@ -0,0 +1,240 @@
+/*
+Tasks are the things work sessions are spent on. The list is kept in tasks.json next to the config file, and
+each work session recorded in history carries the ID of the task that was current at the time.
+*/
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use serde::{Serialize, Deserialize};
+
+use crate::config_manager::ConfigManager;
+use crate::history::{History, SessionOutcome, SessionRecord};
+use crate::timer::TimerSession;
+
+const TASKS_FILE_NAME: &str = "tasks.json";
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskStatus {
+    #[default]
+    Open,
+    Completed,
+    // Hidden from the usual lists, but kept so history can still name it
+    Archived,
+}
+
+impl TaskStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Open => "Open",
+            Self::Completed => "Completed",
+            Self::Archived => "Archived",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Task {
+    pub id: u32,
+    pub name: String,
+    // Number of work sessions the task is expected to take
+    #[serde(default)]
+    pub estimate: Option<u32>,
+    #[serde(default)]
+    pub status: TaskStatus,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct TaskFile {
+    next_id: u32,
+    tasks: Vec<Task>,
+}
+
+pub struct TaskList {
+    path: PathBuf,
+    next_id: u32,
+    tasks: Vec<Task>,
+}
+
+impl TaskList {
+    pub fn open_default() -> Result<Self, String> {
+        let proj_dirs = ConfigManager::get_project_dirs()
+            .ok_or("Could not determine correct config directory")?;
+        let config_dir = proj_dirs.config_dir();
+        fs::create_dir_all(config_dir).map_err(|e| format!("Could not create {}: {e}", config_dir.display()))?;
+        Self::open(config_dir.join(TASKS_FILE_NAME))
+    }
+
+    // Read the list at `path`, starting an empty one if the file doesn't exist yet
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, String> {
+        let path = path.into();
+        let file: TaskFile = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .map_err(|e| format!("Could not read {}: {e}", path.display()))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => TaskFile { next_id: 1, tasks: Vec::new() },
+            Err(e) => return Err(format!("Could not read {}: {e}", path.display())),
+        };
+        Ok(Self { path, next_id: file.next_id, tasks: file.tasks })
+    }
+
+    // Write the list to a temporary file, flush it to disk, then rename it over the old one, so a crash half way
+    // never leaves a torn list behind
+    pub fn save(&self) -> Result<(), String> {
+        let file = TaskFile { next_id: self.next_id, tasks: self.tasks.clone() };
+        let json = serde_json::to_string_pretty(&file).map_err(|e| e.to_string())?;
+        let temp_path = self.path.with_extension(format!("json.tmp-{}", std::process::id()));
+        let write = || -> io::Result<()> {
+            let mut file = File::create(&temp_path)?;
+            file.write_all(json.as_bytes())?;
+            file.sync_all()?;
+            fs::rename(&temp_path, &self.path)
+        };
+        write().map_err(|e| {
+            let _ = fs::remove_file(&temp_path);
+            format!("Could not write {}: {e}", self.path.display())
+        })
+    }
+
+    pub fn tasks(&self) -> &[Task] {
+        &self.tasks
+    }
+
+    // Tasks that can still be worked on
+    pub fn open_tasks(&self) -> impl Iterator<Item = &Task> {
+        self.tasks.iter().filter(|task| task.status == TaskStatus::Open)
+    }
+
+    pub fn get(&self, id: u32) -> Option<&Task> {
+        self.tasks.iter().find(|task| task.id == id)
+    }
+
+    // Look a task up by its ID, or failing that by its name
+    pub fn find(&self, id_or_name: &str) -> Option<&Task> {
+        match id_or_name.trim().parse::<u32>() {
+            Ok(id) => self.get(id),
+            Err(_) => self.tasks.iter().find(|task| task.name.eq_ignore_ascii_case(id_or_name.trim())),
+        }
+    }
+
+    pub fn create(&mut self, name: &str, estimate: Option<u32>) -> Result<u32, String> {
+        let name = valid_name(name)?;
+        // IDs are never reused, so history keeps pointing at the right task
+        let id = self.next_id.max(1);
+        self.next_id = id + 1;
+        self.tasks.push(Task { id, name, estimate, status: TaskStatus::Open });
+        Ok(id)
+    }
+
+    pub fn rename(&mut self, id: u32, name: &str) -> Result<(), String> {
+        let name = valid_name(name)?;
+        self.get_mut(id)?.name = name;
+        Ok(())
+    }
+
+    pub fn set_estimate(&mut self, id: u32, estimate: Option<u32>) -> Result<(), String> {
+        self.get_mut(id)?.estimate = estimate;
+        Ok(())
+    }
+
+    pub fn set_status(&mut self, id: u32, status: TaskStatus) -> Result<(), String> {
+        self.get_mut(id)?.status = status;
+        Ok(())
+    }
+
+    fn get_mut(&mut self, id: u32) -> Result<&mut Task, String> {
+        self.tasks.iter_mut().find(|task| task.id == id).ok_or_else(|| format!("No task with ID {id}"))
+    }
+}
+
+fn valid_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Task name is empty".to_string());
+    }
+    Ok(name.to_string())
+}
+
+// Completed work sessions recorded against each task, to set against its estimate
+pub fn actuals(records: &[SessionRecord]) -> HashMap<u32, u32> {
+    let mut actuals = HashMap::new();
+    for record in records {
+        if let Some(task) = record.task
+            && record.session == TimerSession::Working
+            && record.outcome == SessionOutcome::Completed {
+            *actuals.entry(task).or_insert(0) += 1;
+        }
+    }
+    actuals
+}
+
+// Actuals for every task in the recorded history. Without any history, nothing has been done yet
+pub fn recorded_actuals() -> HashMap<u32, u32> {
+    History::open_default()
+        .and_then(|history| history.records().map_err(|e| e.to_string()))
+        .map(|records| actuals(&records))
+        .unwrap_or_default()
+}
+
+// A table of tasks with the work sessions done on each against their estimate
+pub fn render_table<'a>(tasks: impl Iterator<Item = &'a Task>, actuals: &HashMap<u32, u32>) -> String {
+    let mut table = format!("{:>4}  {:<10} {:>8}  {}\n", "ID", "Status", "Done/Est", "Name");
+    for task in tasks {
+        let done = actuals.get(&task.id).copied().unwrap_or(0);
+        let progress = match task.estimate {
+            Some(estimate) => format!("{done}/{estimate}"),
+            None => format!("{done}/-"),
+        };
+        table.push_str(&format!("{:>4}  {:<10} {:>8}  {}\n", task.id, task.status.as_str(), progress, task.name));
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(task: Option<u32>, session: TimerSession, outcome: SessionOutcome) -> SessionRecord {
+        SessionRecord { task, ..SessionRecord::sample(session, outcome, 0) }
+    }
+
+    #[test]
+    fn tasks_persist_and_keep_their_ids() {
+        let path = std::env::temp_dir().join(format!("rustypomodoro-tasks-test-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut list = TaskList::open(&path).unwrap();
+        let write = list.create("Write report", Some(4)).unwrap();
+        let review = list.create("Review PR", None).unwrap();
+        assert!(list.create("  ", None).is_err());
+        list.rename(review, "Review PRs").unwrap();
+        list.set_status(write, TaskStatus::Archived).unwrap();
+        list.save().unwrap();
+
+        let mut list = TaskList::open(&path).unwrap();
+        assert_eq!(list.find("review prs").map(|t| t.id), Some(review));
+        assert_eq!(list.get(write).unwrap().estimate, Some(4));
+        assert_eq!(list.open_tasks().count(), 1);
+        // A new task never takes an old task's ID
+        assert_eq!(list.create("Plan", None).unwrap(), 3);
+        assert!(list.rename(99, "Missing").is_err());
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn actuals_count_completed_work_per_task() {
+        let records = [
+            record(Some(1), TimerSession::Working, SessionOutcome::Completed),
+            record(Some(1), TimerSession::Working, SessionOutcome::Completed),
+            record(Some(1), TimerSession::Working, SessionOutcome::Aborted),
+            record(Some(2), TimerSession::Working, SessionOutcome::Completed),
+            record(Some(2), TimerSession::Resting, SessionOutcome::Completed),
+            record(None, TimerSession::Working, SessionOutcome::Completed),
+        ];
+        let actuals = actuals(&records);
+        assert_eq!(actuals.get(&1), Some(&2));
+        assert_eq!(actuals.get(&2), Some(&1));
+        assert_eq!(actuals.len(), 2);
+    }
+}
@@ -1,30 +1,89 @@
-use crate::config_manager::Settings;
+use crate::alert::Alert;
+use crate::clock::Clock;
+use crate::config_manager::{Segment, Settings};
+use crate::history::{SessionOutcome, SessionRecord, SCHEMA_VERSION};
+use crate::snapshot::TimerSnapshot;
+use crate::utils::format_duration;
 
-use std::sync::mpsc::{self, Sender, Receiver};
+use serde::{Serialize, Deserialize};
+use std::sync::mpsc::{self, Sender, Receiver, RecvTimeoutError};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 pub enum TimerCommand {
     Pause, 
     Resume, 
     Stop,
     Next,
+    // End the session early, or pass over the one about to start, and start the one after
+    Skip,
+    // Start the current session over from its full length
+    RestartSession,
+    // Add time to the session, whether it is counting down or has just run down
+    Extend(Duration),
+    // New settings, taken up when the next session starts
+    Reconfigure(Box<Settings>),
 }
 
+#[derive(Debug, PartialEq)]
 pub struct TimerEvent {
     pub state: TimerState,
     pub session: TimerSession,
+    // Name of the sequence segment the session belongs to
+    pub segment: String,
     pub remaining: u32,
+    // Planned length of the current session in seconds
+    pub session_length: u32,
+    // Work sessions reached so far in this pass through the sequence, less one. It follows the position in the
+    // sequence: skipping moves it on just as finishing does, restarting leaves it be, and stopping resets it
     pub cycles_complete: u32,
+    // Seconds left in the grace period before the next session starts on its own
+    pub auto_advance_in: Option<u32>,
+    // How many more times the session can be snoozed
+    pub snoozes_left: u32,
+    // Set on the first event after time passed that the timer didn't see
+    pub gap: Option<TimeGap>,
+}
+
+// Time that passed without the timer running through it
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TimeGap {
+    // The machine slept, or the clock jumped forward. The policy it was dealt with by, None if no session
+    // was under way
+    Suspended { seconds: u32, policy: Option<SuspendPolicy> },
+    // The clock was set back. Countdowns run on their own clock, so only the times in history are affected
+    ClockSetBack { seconds: u32 },
+}
+
+impl TimeGap {
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Suspended { seconds, policy } => {
+                let handled = match policy {
+                    Some(SuspendPolicy::Break) => ", taken as a break",
+                    Some(SuspendPolicy::Pause) => ", so the session was paused",
+                    Some(SuspendPolicy::Count) => ", counted towards the session",
+                    None => "",
+                };
+                format!("Away for {}{handled}", format_duration(*seconds))
+            }
+            Self::ClockSetBack { seconds } => format!("The clock was set back by {}", format_duration(*seconds)),
+        }
+    }
 }
 
 pub struct TimerHandle {
     pub cmd_tx: Sender<TimerCommand>,
     pub evt_rx: Receiver<TimerEvent>,
+    // Every session that ends, for the history log
+    pub record_rx: Receiver<SessionRecord>,
+    // Where the timer is, after every change and tick, so the session can be picked up after a crash
+    pub snapshot_rx: Receiver<TimerSnapshot>,
 }
 
 // Enum to keep timer states
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum TimerState {
     Idle,
     CountDown,
@@ -41,9 +100,38 @@ impl TimerState {
             Self::Paused => "Paused",
         }
     }
+
+    // Whether a session is part way through, so stopping now records it as aborted
+    pub fn is_session_under_way(&self) -> bool {
+        matches!(self, Self::CountDown | Self::Paused)
+    }
+}
+
+// What a session makes of time the machine spent asleep part way through it
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SuspendPolicy {
+    // End the session where the gap began, and log the gap as a break taken
+    Break,
+    // Pause the session where the gap began
+    #[default]
+    Pause,
+    // Let the session run on through the gap
+    Count,
+}
+
+impl SuspendPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Break => "Break",
+            Self::Pause => "Pause",
+            Self::Count => "Count",
+        }
+    }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum TimerSession {
     Working,
     Resting,
@@ -60,90 +148,501 @@ impl TimerSession {
     }
 }
 
-pub struct Timer {
+pub struct Timer<C: Clock> {
+    clock: C,
+    alert: Alert,
     timer_settings: Settings,
+    // Segments walked in order, and where in them we are
+    sequence: Vec<Segment>,
+    segment_index: usize,
     current_state: TimerState,
     current_session: TimerSession,
     time_remaining: u32,
+    session_length: u32,
     cycles_complete: u32,
+    // Monotonic point at which the current countdown ends. Pausing pushes it back by the paused time
+    deadline: Option<Instant>,
+    paused_at: Option<Instant>,
+    // Set while Waiting if the upcoming session should start by itself at this point
+    auto_advance_at: Option<Instant>,
+    // Next time the session-end alert should be replayed while Waiting
+    alert_at: Option<Instant>,
+    // When the current session began, and how long it has spent paused so far
+    session_started_at: Instant,
+    session_started_wall: SystemTime,
+    paused_total: Duration,
+    // Time the session ran that the monotonic clock didn't see: before it was picked up from a snapshot, or
+    // with the machine asleep
+    unseen: Duration,
+    // Both clocks as last looked at, to spot time that only one of them saw pass
+    last_seen: (Instant, SystemTime),
+    // Time that passed unseen since the last event
+    gap: Option<TimeGap>,
+    // Snoozes taken this session, and the seconds they added to it
+    snoozes: u32,
+    extended: u32,
+    // The session that just ran down and when. Held back from history while Waiting, as a snooze may carry it on
+    ran_down: Option<(SessionRecord, Instant)>,
+    // Sessions that ended since the owner last collected them
+    finished: Vec<SessionRecord>,
+    // Settings waiting for the current session to end
+    pending_settings: Option<Settings>,
 }
 
-impl Timer {
-    pub fn new(settings: Settings) -> Self {
+impl<C: Clock> Timer<C> {
+    pub fn new(settings: Settings, clock: C) -> Self {
         Self {
+            alert: Alert::from_settings(&settings),
+            sequence: settings.sequence(),
+            segment_index: 0,
             timer_settings: settings,
             current_state: TimerState::Idle,
             current_session: TimerSession::Working,
             time_remaining: 0,
+            session_length: 0,
             cycles_complete: 0,
+            deadline: None,
+            paused_at: None,
+            auto_advance_at: None,
+            alert_at: None,
+            session_started_at: clock.now(),
+            session_started_wall: clock.system_now(),
+            paused_total: Duration::ZERO,
+            unseen: Duration::ZERO,
+            last_seen: (clock.now(), clock.system_now()),
+            gap: None,
+            snoozes: 0,
+            extended: 0,
+            ran_down: None,
+            finished: Vec::new(),
+            pending_settings: None,
+            clock,
+        }
+    }
+
+    // Pick a session up from a snapshot, in the state it was left in. The time the app was down counts as
+    // time spent paused
+    pub fn from_snapshot(snapshot: TimerSnapshot, clock: C) -> Self {
+        let pending_record = snapshot.pending_record.then(|| snapshot.session_record());
+        let mut timer = Self::new(snapshot.settings, clock);
+        let now = timer.clock.now();
+        let wall_now = unix_seconds(timer.clock.system_now());
+        let down_for = Duration::from_secs(wall_now.saturating_sub(snapshot.saved_at).max(0) as u64);
+
+        timer.segment_index = snapshot.segment_index.min(timer.sequence.len() - 1);
+        timer.current_session = snapshot.session;
+        timer.time_remaining = snapshot.remaining;
+        timer.session_length = snapshot.session_length;
+        timer.cycles_complete = snapshot.cycles_complete;
+        timer.snoozes = snapshot.snoozes;
+        timer.extended = snapshot.extended_seconds;
+        timer.session_started_wall = UNIX_EPOCH + Duration::from_secs(snapshot.started_at.max(0) as u64);
+        timer.unseen = Duration::from_secs(wall_now.saturating_sub(snapshot.started_at).max(0) as u64);
+        timer.paused_total = Duration::from_secs(snapshot.paused_seconds.into()) + down_for;
+        match snapshot.state {
+            TimerState::CountDown | TimerState::Paused => {
+                timer.current_state = snapshot.state;
+                timer.deadline = Some(now + Duration::from_secs(snapshot.remaining.into()));
+                if snapshot.state == TimerState::Paused {
+                    timer.paused_at = Some(now);
+                }
+            }
+            TimerState::Waiting | TimerState::Idle => {
+                timer.current_state = TimerState::Waiting;
+                timer.time_remaining = 0;
+                timer.ran_down = pending_record.map(|record| (record, now));
+                timer.schedule_auto_advance();
+            }
         }
+        timer
     }
 
     fn prepare_start(&mut self) {
         self.current_state = TimerState::CountDown;
-        self.current_session = TimerSession::Working;
-        self.time_remaining = self.timer_settings.work_seconds;
-        self.cycles_complete = 0;
+        self.enter_segment(0);
+        self.start_countdown();
+    }
+
+    fn enter_segment(&mut self, index: usize) {
+        let segment = &self.sequence[index];
+        self.segment_index = index;
+        self.current_session = segment.session;
+        self.time_remaining = segment.seconds;
+        // The cycle is the work session we are on or have just finished, counted through this pass
+        let work_so_far = self.sequence[..=index].iter().filter(|s| s.session == TimerSession::Working).count();
+        self.cycles_complete = (work_so_far as u32).saturating_sub(1);
+    }
+
+    fn start_countdown(&mut self) {
+        self.session_length = self.time_remaining;
+        self.session_started_at = self.clock.now();
+        self.session_started_wall = self.clock.system_now();
+        self.paused_total = Duration::ZERO;
+        self.unseen = Duration::ZERO;
+        self.snoozes = 0;
+        self.extended = 0;
+        self.deadline = Some(self.clock.now() + Duration::from_secs(self.time_remaining.into()));
+        self.paused_at = None;
+    }
+
+    // Time left until the deadline, frozen at the moment of pausing while paused
+    fn remaining_duration(&self) -> Duration {
+        match self.deadline {
+            Some(deadline) => deadline.saturating_duration_since(self.paused_at.unwrap_or_else(|| self.clock.now())),
+            None => Duration::ZERO,
+        }
+    }
+
+    fn remaining_grace(&self) -> Option<Duration> {
+        self.auto_advance_at.map(|at| at.saturating_duration_since(self.clock.now()))
+    }
+
+    // When the timer next needs to wake: the displayed remaining time (or grace countdown) changing, or an
+    // alert replay. None if there is nothing to wait for but commands
+    fn next_tick_at(&self) -> Option<Instant> {
+        let remaining = match self.current_state {
+            TimerState::CountDown => Some(self.remaining_duration()),
+            TimerState::Waiting => self.remaining_grace(),
+            _ => None,
+        };
+        let next_second = remaining.map(|remaining| {
+            let into_second = Duration::from_nanos((remaining.as_nanos() % 1_000_000_000) as u64);
+            let wait = if into_second.is_zero() {
+                Duration::from_secs(1).min(remaining)
+            } else {
+                into_second
+            };
+            self.clock.now() + wait
+        });
+        match (next_second, self.alert_at) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
     }
 
     fn tick(&mut self) {
         if let TimerState::CountDown = self.current_state {
-            if self.time_remaining > 0 {
-                self.time_remaining -= 1;
-            } else {
+            let remaining = self.remaining_duration();
+            self.time_remaining = whole_seconds(remaining);
+            if remaining.is_zero() {
                 self.current_state = TimerState::Waiting;
-                    // TODO: Play Audio on interval until input - non blocking? - needs to loop? remember tick is only continously
-                    // Called if in countdown, which we wont be here
+                self.deadline = None;
+                self.ran_down = Some((self.session_record(SessionOutcome::Completed), self.clock.now()));
+                self.play_alert();
+                self.schedule_auto_advance();
+                // Even with no grace, the next session waits for the next tick so the Waiting event goes out first
+                return;
+            }
+        }
+
+        if let TimerState::Waiting = self.current_state {
+            if self.alert_at.is_some_and(|at| self.clock.now() >= at) {
+                self.play_alert();
+            }
+            if self.remaining_grace().is_some_and(|grace| grace.is_zero()) {
+                self.next_session();
             }
         }
     }
 
+    // Look for time the monotonic clock didn't see pass (on most platforms it stops while the machine sleeps),
+    // or saw all at once, long after `expected_wake`
+    fn check_for_gap(&mut self, expected_wake: Option<Instant>) {
+        let (now, wall_now) = (self.clock.now(), self.clock.system_now());
+        let (last, last_wall) = std::mem::replace(&mut self.last_seen, (now, wall_now));
+        let threshold = Duration::from_secs(self.timer_settings.suspend_threshold_seconds.into());
+        let wall = match wall_now.duration_since(last_wall) {
+            Ok(wall) => wall.as_nanos() as i128,
+            Err(e) => -(e.duration().as_nanos() as i128),
+        };
+        let skew = wall - now.saturating_duration_since(last).as_nanos() as i128;
+
+        if skew >= threshold.as_nanos() as i128 {
+            self.handle_suspend(Duration::from_nanos(skew as u64), false);
+        } else if -skew >= threshold.as_nanos() as i128 {
+            self.gap = Some(TimeGap::ClockSetBack { seconds: Duration::from_nanos(-skew as u64).as_secs() as u32 });
+        } else if let Some(at) = expected_wake.filter(|at| now >= *at + threshold) {
+            self.handle_suspend(now - at, true);
+        }
+    }
+
+    // Deal with the machine having slept for `gap`, which the monotonic clock may or may not have counted
+    fn handle_suspend(&mut self, gap: Duration, counted: bool) {
+        let under_way = matches!(self.current_state, TimerState::CountDown | TimerState::Paused);
+        let policy = under_way.then_some(self.timer_settings.suspend_policy);
+        self.gap = Some(TimeGap::Suspended { seconds: gap.as_secs() as u32, policy });
+
+        match policy {
+            // A break already under way just runs on
+            Some(SuspendPolicy::Break) if self.current_session != TimerSession::Break => self.take_gap_as_break(gap, counted),
+            Some(SuspendPolicy::Pause) => {
+                self.take_gap_as_paused(gap, counted);
+                if self.current_state == TimerState::CountDown {
+                    self.current_state = TimerState::Paused;
+                    self.paused_at = Some(self.clock.now());
+                }
+            }
+            // Time spent paused stays paused
+            _ if self.current_state == TimerState::Paused => self.take_gap_as_paused(gap, counted),
+            Some(_) if !counted => {
+                // Bring the deadline forward by what the monotonic clock missed
+                self.unseen += gap;
+                let now = self.clock.now();
+                self.deadline = self.deadline.map(|deadline| deadline.checked_sub(gap).unwrap_or(now).max(now));
+            }
+            _ => {}
+        }
+    }
+
+    fn take_gap_as_paused(&mut self, gap: Duration, counted: bool) {
+        if !counted {
+            self.unseen += gap;
+            self.paused_total += gap;
+        } else if self.paused_at.is_none() {
+            // Paused time is covered by paused_at already
+            self.deadline = self.deadline.map(|deadline| deadline + gap);
+            self.paused_total += gap;
+        }
+    }
+
+    // End the session where the gap began and log the gap as a break, then wait to start whatever follows the
+    // next break in the sequence
+    fn take_gap_as_break(&mut self, gap: Duration, counted: bool) {
+        if counted {
+            self.session_started_at += gap;
+            self.paused_at = self.paused_at.map(|at| at + gap);
+        }
+        self.finish_session(SessionOutcome::Interrupted);
+
+        let len = self.sequence.len();
+        let break_index = (0..len).map(|i| (self.segment_index + i) % len)
+            .find(|i| self.sequence[*i].session == TimerSession::Break);
+        self.enter_segment(break_index.unwrap_or(len - 1));
+        self.session_length = self.time_remaining;
+        self.time_remaining = 0;
+        self.current_state = TimerState::Waiting;
+        self.deadline = None;
+        self.paused_at = None;
+        self.schedule_auto_advance();
+
+        let ended_at = unix_seconds(self.clock.system_now());
+        self.finished.push(SessionRecord {
+            schema: SCHEMA_VERSION,
+            session: TimerSession::Break,
+            outcome: SessionOutcome::Completed,
+            planned_seconds: if break_index.is_some() { self.session_length } else { gap.as_secs() as u32 },
+            actual_seconds: gap.as_secs() as u32,
+            paused_seconds: 0,
+            extended_seconds: 0,
+            started_at: ended_at - gap.as_secs() as i64,
+            ended_at,
+            cycle: self.cycles_complete,
+            task: None,
+        });
+    }
+
+    // Play the alert and schedule the next replay, which keeps going until the session is advanced
+    fn play_alert(&mut self) {
+        self.alert.play();
+        self.alert_at = self.alert.repeat_every().map(|every| self.clock.now() + every);
+    }
+
+    // Index of the segment after the current one, going back to the start after the last
+    fn upcoming_index(&self) -> usize {
+        (self.segment_index + 1) % self.sequence.len()
+    }
+
+    fn should_auto_advance(&self) -> bool {
+        self.sequence[self.upcoming_index()].auto_advance
+    }
+
+    // Start the grace period if the upcoming session starts by itself
+    fn schedule_auto_advance(&mut self) {
+        if self.should_auto_advance() {
+            let grace = Duration::from_secs(self.timer_settings.auto_advance_grace_seconds.into());
+            self.auto_advance_at = Some(self.clock.now() + grace);
+        }
+    }
+
     fn next_session(&mut self) {
         if let TimerState::Waiting = self.current_state {
-            match self.current_session {
-                TimerSession::Working => {
-                    if self.cycles_complete == self.timer_settings.work_relief_cycles-1 {
-                        self.current_session = TimerSession::Break;
-                        self.time_remaining = self.timer_settings.break_seconds;
-                    } else {
-                        self.current_session = TimerSession::Resting;
-                        self.time_remaining = self.timer_settings.relief_seconds;
-                    }
-                }
-                TimerSession::Break | TimerSession::Resting => {
-                    if matches!(self.current_session, TimerSession::Break) {self.cycles_complete = 0}
-                    else {self.cycles_complete += 1}
-                    self.current_session = TimerSession::Working;
-                    self.time_remaining = self.timer_settings.work_seconds;
-                }
+            self.start_session(true);
+        }
+    }
+
+    // Start counting down the next segment, or the current one over again, on the pending settings if there are any
+    fn start_session(&mut self, next: bool) {
+        self.record_ran_down();
+        if let Some(settings) = self.pending_settings.take() {
+            self.apply_settings(settings);
+        }
+        self.enter_segment(if next { self.upcoming_index() } else { self.segment_index });
+        self.current_state = TimerState::CountDown;
+        self.auto_advance_at = None;
+        self.alert_at = None;
+        self.start_countdown();
+    }
+
+    // Skipped sessions go into history as such. One that has run down is done, so what gets skipped from
+    // Waiting is the session about to start
+    fn skip(&mut self) {
+        match self.current_state {
+            TimerState::CountDown | TimerState::Paused => self.finish_session(SessionOutcome::Skipped),
+            TimerState::Waiting => {
+                self.start_session(true);
+                self.finish_session(SessionOutcome::Skipped);
             }
-            self.current_state = TimerState::CountDown;
+            TimerState::Idle => return,
+        }
+        self.start_session(true);
+    }
+
+    // A session cut short by restarting goes into history as aborted
+    fn restart_session(&mut self) {
+        match self.current_state {
+            TimerState::CountDown | TimerState::Paused => self.finish_session(SessionOutcome::Aborted),
+            TimerState::Waiting => {}
+            TimerState::Idle => return,
+        }
+        self.start_session(false);
+    }
+
+    // The session that ran down is over for good, so it can go into history
+    fn record_ran_down(&mut self) {
+        if let Some((record, _)) = self.ran_down.take() {
+            self.finished.push(record);
         }
     }
 
+    // Add time to the session, as long as it has snoozes left. A session that has just run down is carried on
+    // where it left off
+    fn extend(&mut self, by: Duration) {
+        if self.snoozes_left() == 0 {
+            return;
+        }
+        let now = self.clock.now();
+        match self.current_state {
+            TimerState::CountDown | TimerState::Paused => self.deadline = self.deadline.map(|deadline| deadline + by),
+            _ => {
+                let Some((_, ran_down_at)) = self.ran_down.take() else { return };
+                // Waiting for input is time away from the session
+                self.paused_total += now.saturating_duration_since(ran_down_at);
+                self.current_state = TimerState::CountDown;
+                self.deadline = Some(now + by);
+                self.auto_advance_at = None;
+                self.alert_at = None;
+            }
+        }
+        let seconds = by.as_secs() as u32;
+        self.time_remaining = whole_seconds(self.remaining_duration());
+        self.session_length += seconds;
+        self.extended += seconds;
+        self.snoozes += 1;
+    }
+
+    fn snoozes_left(&self) -> u32 {
+        let snoozable = match self.current_state {
+            TimerState::CountDown | TimerState::Paused => true,
+            TimerState::Waiting => self.ran_down.is_some(),
+            TimerState::Idle => false,
+        };
+        if snoozable { self.timer_settings.max_snoozes.saturating_sub(self.snoozes) } else { 0 }
+    }
+
+    // Switch to new settings, carrying on from the same place in the sequence where it still exists and
+    // starting it over where it doesn't
+    fn apply_settings(&mut self, settings: Settings) {
+        self.alert = Alert::from_settings(&settings);
+        self.sequence = settings.sequence();
+        if self.segment_index >= self.sequence.len() {
+            self.segment_index = self.sequence.len() - 1;
+        }
+        self.timer_settings = settings;
+    }
+
     fn pause(&mut self) {
         if let TimerState::CountDown = self.current_state {
             self.current_state = TimerState::Paused;
+            self.paused_at = Some(self.clock.now());
         }
     }
 
     fn resume(&mut self) {
         if let TimerState::Paused = self.current_state {
             self.current_state = TimerState::CountDown;
+            // Subtract the paused time by moving the deadline back
+            if let (Some(deadline), Some(paused_at)) = (self.deadline, self.paused_at.take()) {
+                let paused_for = self.clock.now().saturating_duration_since(paused_at);
+                self.deadline = Some(deadline + paused_for);
+                self.paused_total += paused_for;
+            }
         }
     }
 
-    fn stop(&mut self) -> bool {
-        // We only stop if paused or waiting
-        match self.current_state {
-            TimerState::Paused | TimerState::Waiting => {
-                self.current_state = TimerState::Idle;
-                self.time_remaining = 0;
-                self.cycles_complete = 0;
-                true
-            }
-            _ => false
-        }   
+    fn stop(&mut self) {
+        if self.current_state.is_session_under_way() {
+            self.finish_session(SessionOutcome::Aborted);
+        }
+        self.record_ran_down();
+        self.current_state = TimerState::Idle;
+        self.time_remaining = 0;
+        self.cycles_complete = 0;
+        self.deadline = None;
+        self.paused_at = None;
+        self.auto_advance_at = None;
+        self.alert_at = None;
+    }
+
+    // Record the current session as over
+    fn finish_session(&mut self, outcome: SessionOutcome) {
+        let record = self.session_record(outcome);
+        self.finished.push(record);
+    }
+
+    // The current session as it stands, ending now
+    fn session_record(&self, outcome: SessionOutcome) -> SessionRecord {
+        let now = self.clock.now();
+        let paused = self.paused_so_far(now);
+        let actual = self.unseen + now.saturating_duration_since(self.session_started_at);
+        let started_at = unix_seconds(self.session_started_wall);
+
+        SessionRecord {
+            schema: SCHEMA_VERSION,
+            session: self.current_session,
+            outcome,
+            planned_seconds: self.session_length.saturating_sub(self.extended),
+            actual_seconds: actual.as_secs() as u32,
+            paused_seconds: paused.as_secs() as u32,
+            extended_seconds: self.extended,
+            started_at,
+            ended_at: started_at + actual.as_secs() as i64,
+            cycle: self.cycles_complete,
+            task: None,
+        }
+    }
+
+    fn paused_so_far(&self, now: Instant) -> Duration {
+        self.paused_total + self.paused_at.map_or(Duration::ZERO, |at| now.saturating_duration_since(at))
+    }
+
+    fn snapshot(&self) -> TimerSnapshot {
+        TimerSnapshot {
+            settings: self.timer_settings.clone(),
+            segment_index: self.segment_index,
+            state: self.current_state,
+            session: self.current_session,
+            segment: self.sequence[self.segment_index].name.clone(),
+            remaining: self.time_remaining,
+            session_length: self.session_length,
+            cycles_complete: self.cycles_complete,
+            snoozes: self.snoozes,
+            extended_seconds: self.extended,
+            pending_record: self.ran_down.is_some(),
+            started_at: unix_seconds(self.session_started_wall),
+            saved_at: unix_seconds(self.clock.system_now()),
+            paused_seconds: self.paused_so_far(self.clock.now()).as_secs() as u32,
+            task: None,
+        }
     }
 
     fn get_state(&self) -> TimerState {
@@ -162,46 +661,504 @@ impl Timer {
         self.cycles_complete
     }
 
-    pub fn spawn(settings: Settings) -> TimerHandle {
+    fn get_event(&self) -> TimerEvent {
+        TimerEvent {
+            state: self.get_state(),
+            session: self.get_session(),
+            segment: self.sequence[self.segment_index].name.clone(),
+            remaining: self.get_remaining(),
+            session_length: self.session_length,
+            cycles_complete: self.get_cycles_complete(),
+            auto_advance_in: self.remaining_grace().map(whole_seconds),
+            snoozes_left: self.snoozes_left(),
+            gap: self.gap,
+        }
+    }
+
+    pub fn spawn(settings: Settings, clock: C) -> TimerHandle {
+        let mut timer = Timer::new(settings, clock);
+        timer.prepare_start();
+        timer.run()
+    }
+
+    pub fn spawn_resumed(snapshot: TimerSnapshot, clock: C) -> TimerHandle {
+        Timer::from_snapshot(snapshot, clock).run()
+    }
+
+    // Run the timer on its own thread until it is stopped or nobody is left to control it
+    fn run(self) -> TimerHandle {
         let (cmd_tx, cmd_rx) = mpsc::channel::<TimerCommand>();
         let (evt_tx, evt_rx) = mpsc::channel::<TimerEvent>();
+        let (record_tx, record_rx) = mpsc::channel::<SessionRecord>();
+        let (snapshot_tx, snapshot_rx) = mpsc::channel::<TimerSnapshot>();
 
         thread::spawn(move || {
-            let mut timer = Timer::new(settings);
-            timer.prepare_start();
+            let mut timer = self;
 
             loop {
-                // 1. Handle commands if any
-                if let Ok(cmd) = cmd_rx.try_recv() {
-                    match cmd {
-                        TimerCommand::Pause => timer.pause(),
-                        TimerCommand::Resume => timer.resume(),
-                        TimerCommand::Next => timer.next_session(),
-                        TimerCommand::Stop => {
-                            if timer.stop() {
-                                break;
-                            }
-                        },
-                    }
-                }
+                // Work out the next whole-second boundary before publishing, so whoever reacts to the
+                // event can never move the clock past a boundary we haven't scheduled yet
+                let next_tick = timer.next_tick_at();
 
-                // 2. Tick if counting down
-                if let TimerState::CountDown = timer.get_state() {
-                    timer.tick();
+                for record in timer.finished.drain(..) {
+                    let _ = record_tx.send(record);
                 }
 
                 // send status (best-effort)
-                let _ = evt_tx.send(TimerEvent {
-                    state: timer.get_state(),  
-                    session: timer.get_session(),
-                    remaining: timer.get_remaining(),
-                    cycles_complete: timer.get_cycles_complete(),
-                });
-
-                thread::sleep(Duration::from_secs(1));
+                let _ = evt_tx.send(timer.get_event());
+                timer.gap = None;
+                let _ = snapshot_tx.send(timer.snapshot());
+
+                // 1. Wait for a command, but no longer than the next whole-second boundary of the countdown
+                let received = timer.clock.recv_until(&cmd_rx, next_tick);
+                // Catch up on any time we missed before acting on the command
+                timer.check_for_gap(next_tick);
+                match received {
+                    Ok(TimerCommand::Pause) => timer.pause(),
+                    Ok(TimerCommand::Resume) => timer.resume(),
+                    Ok(TimerCommand::Next) => timer.next_session(),
+                    Ok(TimerCommand::Extend(by)) => timer.extend(by),
+                    Ok(TimerCommand::Reconfigure(settings)) => timer.pending_settings = Some(*settings),
+                    Ok(TimerCommand::Skip) => timer.skip(),
+                    Ok(TimerCommand::RestartSession) => timer.restart_session(),
+                    Ok(TimerCommand::Stop) => {
+                        timer.stop();
+                        for record in timer.finished.drain(..) {
+                            let _ = record_tx.send(record);
+                        }
+                        break;
+                    },
+                    Err(RecvTimeoutError::Timeout) => {},
+                    // Nobody is left to control the timer
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+
+                // 2. Bring the countdown up to date with the deadline
+                timer.tick();
             }
         });
 
-        TimerHandle { cmd_tx, evt_rx }
+        TimerHandle { cmd_tx, evt_rx, record_rx, snapshot_rx }
+    }
+}
+
+fn unix_seconds(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64)
+}
+
+// Round up so the display reads 00:01 until the final second has fully elapsed
+fn whole_seconds(duration: Duration) -> u32 {
+    duration.as_secs() as u32 + u32::from(duration.subsec_nanos() > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alert::AlertOutput;
+    use crate::clock::ManualClock;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    fn test_settings() -> Settings {
+        Settings { work_seconds: 3, relief_seconds: 1, break_seconds: 2, work_relief_cycles: 2, alert_output: AlertOutput::Null, max_snoozes: 0, ..Default::default() }
+    }
+
+    fn event(state: TimerState, session: TimerSession, remaining: u32, cycles_complete: u32) -> TimerEvent {
+        let (segment, session_length) = match session {
+            TimerSession::Working => ("Work", 3),
+            TimerSession::Resting => ("Eye relief", 1),
+            TimerSession::Break => ("Break", 2),
+        };
+        let segment = segment.to_string();
+        TimerEvent { state, session, segment, remaining, session_length, cycles_complete, auto_advance_in: None, snoozes_left: 0, gap: None }
+    }
+
+    fn started_timer(clock: &ManualClock) -> Timer<ManualClock> {
+        let mut timer = Timer::new(test_settings(), clock.clone());
+        timer.prepare_start();
+        timer
+    }
+
+    // Let virtual time pass one second at a time, ticking like the timer thread would
+    fn run_for(timer: &mut Timer<ManualClock>, clock: &ManualClock, seconds: u32) {
+        for _ in 0..seconds {
+            clock.advance(SECOND);
+            timer.tick();
+        }
+    }
+
+    #[test]
+    fn tick_counts_down_then_waits() {
+        let clock = ManualClock::new();
+        let mut timer = started_timer(&clock);
+
+        run_for(&mut timer, &clock, 2);
+        assert_eq!(timer.get_event(), event(TimerState::CountDown, TimerSession::Working, 1, 0));
+
+        run_for(&mut timer, &clock, 1);
+        assert_eq!(timer.get_event(), event(TimerState::Waiting, TimerSession::Working, 0, 0));
+    }
+
+    #[test]
+    fn tick_rounds_partial_seconds_up() {
+        let clock = ManualClock::new();
+        let mut timer = started_timer(&clock);
+
+        clock.advance(Duration::from_millis(2500));
+        timer.tick();
+        assert_eq!(timer.get_remaining(), 1);
+        assert_eq!(timer.next_tick_at(), Some(clock.now() + Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn paused_time_is_not_counted() {
+        let clock = ManualClock::new();
+        let mut timer = started_timer(&clock);
+
+        run_for(&mut timer, &clock, 1);
+        timer.pause();
+        run_for(&mut timer, &clock, 10);
+        assert_eq!(timer.get_event(), event(TimerState::Paused, TimerSession::Working, 2, 0));
+        assert_eq!(timer.next_tick_at(), None);
+
+        timer.resume();
+        run_for(&mut timer, &clock, 1);
+        assert_eq!(timer.get_event(), event(TimerState::CountDown, TimerSession::Working, 1, 0));
+    }
+
+    #[test]
+    fn next_session_only_advances_when_waiting() {
+        let clock = ManualClock::new();
+        let mut timer = started_timer(&clock);
+
+        timer.next_session();
+        assert_eq!(timer.get_event(), event(TimerState::CountDown, TimerSession::Working, 3, 0));
+
+        run_for(&mut timer, &clock, 3);
+        timer.next_session();
+        assert_eq!(timer.get_event(), event(TimerState::CountDown, TimerSession::Resting, 1, 0));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn next_session_walks_work_relief_break_cycle() {
+        let clock = ManualClock::new();
+        let mut timer = started_timer(&clock);
+        let mut sessions = Vec::new();
+
+        for _ in 0..5 {
+            let remaining = timer.get_remaining();
+            run_for(&mut timer, &clock, remaining);
+            timer.next_session();
+            sessions.push((timer.get_session(), timer.get_cycles_complete()));
+        }
+
+        assert_eq!(sessions, vec![
+            (TimerSession::Resting, 0),
+            (TimerSession::Working, 1),
+            (TimerSession::Break, 1),
+            (TimerSession::Working, 0),
+            (TimerSession::Resting, 0),
+        ]);
+    }
+
+    #[test]
+    fn auto_advance_waits_out_grace_then_starts_session() {
+        let clock = ManualClock::new();
+        let settings = Settings { auto_start_relief: true, auto_advance_grace_seconds: 2, ..test_settings() };
+        let mut timer = Timer::new(settings, clock.clone());
+        timer.prepare_start();
+
+        run_for(&mut timer, &clock, 3);
+        assert_eq!(timer.get_event().auto_advance_in, Some(2));
+        run_for(&mut timer, &clock, 1);
+        assert_eq!(timer.get_event().auto_advance_in, Some(1));
+        run_for(&mut timer, &clock, 1);
+        assert_eq!(timer.get_event(), event(TimerState::CountDown, TimerSession::Resting, 1, 0));
+
+        // Work still needs confirming
+        run_for(&mut timer, &clock, 5);
+        assert_eq!(timer.get_event(), event(TimerState::Waiting, TimerSession::Resting, 0, 0));
+        assert_eq!(timer.next_tick_at(), None);
+
+        // Without a grace period the session still shows as ended for a tick, so its Waiting event goes out
+        let settings = Settings { auto_start_relief: true, ..test_settings() };
+        let mut timer = Timer::new(settings, clock.clone());
+        timer.prepare_start();
+        run_for(&mut timer, &clock, 3);
+        assert_eq!(timer.get_event().state, TimerState::Waiting);
+        assert_eq!(timer.next_tick_at(), Some(clock.now()));
+        timer.tick();
+        assert_eq!(timer.get_event(), event(TimerState::CountDown, TimerSession::Resting, 1, 0));
+    }
+
+    #[test]
+    fn custom_sequence_is_walked_in_order() {
+        let clock = ManualClock::new();
+        let sequence = vec![
+            Segment::new("Write", TimerSession::Working, 2, false),
+            Segment::new("Stretch", TimerSession::Break, 1, true),
+            Segment::new("Review", TimerSession::Working, 2, false),
+            Segment::new("Relief", TimerSession::Resting, 1, false),
+            Segment::new("Walk", TimerSession::Break, 3, false),
+        ];
+        let mut timer = Timer::new(Settings { sequence, ..test_settings() }, clock.clone());
+        timer.prepare_start();
+        let mut walked = vec![(timer.get_event().segment, timer.get_cycles_complete())];
+
+        for _ in 0..5 {
+            let remaining = timer.get_remaining();
+            run_for(&mut timer, &clock, remaining);
+            // Only the stretch starts by itself
+            if timer.get_state() == TimerState::Waiting {
+                timer.next_session();
+            }
+            let event = timer.get_event();
+            assert_eq!(event.state, TimerState::CountDown);
+            walked.push((event.segment, event.cycles_complete));
+        }
+
+        let expected = [("Write", 0), ("Stretch", 0), ("Review", 1), ("Relief", 1), ("Walk", 1), ("Write", 0)];
+        assert_eq!(walked, expected.map(|(name, cycles)| (name.to_string(), cycles)));
+    }
+
+    #[test]
+    fn new_settings_wait_for_the_next_session() {
+        let clock = ManualClock::new();
+        let mut timer = started_timer(&clock);
+        run_for(&mut timer, &clock, 1);
+
+        timer.pending_settings = Some(Settings { work_seconds: 10, relief_seconds: 4, ..test_settings() });
+        run_for(&mut timer, &clock, 1);
+        assert_eq!(timer.get_event(), event(TimerState::CountDown, TimerSession::Working, 1, 0));
+
+        run_for(&mut timer, &clock, 1);
+        timer.next_session();
+        let event = timer.get_event();
+        assert_eq!((event.session, event.remaining, event.session_length), (TimerSession::Resting, 4, 4));
+        assert!(timer.pending_settings.is_none());
+
+        // A sequence too short to carry on in starts over
+        run_for(&mut timer, &clock, 4);
+        timer.pending_settings = Some(Settings { sequence: vec![Segment::new("Focus", TimerSession::Working, 5, false)], ..test_settings() });
+        timer.next_session();
+        assert_eq!((timer.get_event().segment, timer.get_remaining()), ("Focus".to_string(), 5));
+    }
+
+    #[test]
+    fn snoozes_extend_the_session_up_to_the_limit() {
+        let clock = ManualClock::new();
+        let mut timer = Timer::new(Settings { max_snoozes: 2, ..test_settings() }, clock.clone());
+        timer.prepare_start();
+
+        run_for(&mut timer, &clock, 1);
+        timer.extend(SECOND * 5);
+        let snoozed = timer.get_event();
+        assert_eq!((snoozed.remaining, snoozed.session_length, snoozed.snoozes_left), (7, 8, 1));
+
+        // Carried on after running down, with the wait counted as paused
+        run_for(&mut timer, &clock, 7);
+        run_for(&mut timer, &clock, 4);
+        assert!(timer.finished.is_empty());
+        timer.extend(SECOND * 2);
+        assert_eq!(timer.get_event(), TimerEvent { session_length: 10, ..event(TimerState::CountDown, TimerSession::Working, 2, 0) });
+        timer.extend(SECOND * 2);
+        assert_eq!(timer.get_remaining(), 2);
+
+        run_for(&mut timer, &clock, 2);
+        timer.next_session();
+        let record = &timer.finished[0];
+        assert_eq!((record.planned_seconds, record.extended_seconds, record.actual_seconds, record.paused_seconds), (3, 7, 14, 4));
+    }
+
+    #[test]
+    fn alert_repeats_until_session_is_advanced() {
+        let log = std::env::temp_dir().join(format!("rustypomodoro-alert-test-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&log);
+        let alerts_played = || std::fs::read_to_string(&log).map(|s| s.lines().count()).unwrap_or(0);
+
+        let clock = ManualClock::new();
+        let settings = Settings { alert_output: AlertOutput::File(log.clone()), alert_repeat_seconds: 10, ..test_settings() };
+        let mut timer = Timer::new(settings, clock.clone());
+        timer.prepare_start();
+
+        run_for(&mut timer, &clock, 2);
+        assert_eq!(alerts_played(), 0);
+        run_for(&mut timer, &clock, 1);
+        assert_eq!(alerts_played(), 1);
+        assert_eq!(timer.next_tick_at(), Some(clock.now() + Duration::from_secs(10)));
+        run_for(&mut timer, &clock, 10);
+        assert_eq!(alerts_played(), 2);
+
+        timer.next_session();
+        timer.pause();
+        run_for(&mut timer, &clock, 30);
+        assert_eq!(alerts_played(), 2);
+        let _ = std::fs::remove_file(&log);
+    }
+
+    #[test]
+    fn finished_sessions_are_recorded() {
+        let clock = ManualClock::new();
+        let mut timer = started_timer(&clock);
+        let started_at = unix_seconds(clock.system_now());
+
+        run_for(&mut timer, &clock, 1);
+        timer.pause();
+        run_for(&mut timer, &clock, 4);
+        timer.resume();
+        run_for(&mut timer, &clock, 2);
+        timer.next_session();
+        timer.pause();
+        run_for(&mut timer, &clock, 1);
+        timer.stop();
+
+        let outcomes: Vec<_> = timer.finished.iter()
+            .map(|r| (r.session, r.outcome, r.planned_seconds, r.actual_seconds, r.paused_seconds, r.cycle))
+            .collect();
+        assert_eq!(outcomes, vec![
+            (TimerSession::Working, SessionOutcome::Completed, 3, 7, 4, 0),
+            (TimerSession::Resting, SessionOutcome::Aborted, 1, 1, 1, 0),
+        ]);
+        assert_eq!((timer.finished[0].started_at, timer.finished[0].ended_at), (started_at, started_at + 7));
+        assert_eq!(timer.finished[1].started_at, started_at + 7);
+    }
+
+    #[test]
+    fn snapshot_picks_up_where_it_left_off() {
+        let clock = ManualClock::new();
+        let mut timer = started_timer(&clock);
+        let started_at = unix_seconds(clock.system_now());
+        run_for(&mut timer, &clock, 1);
+        timer.pause();
+        run_for(&mut timer, &clock, 2);
+        let snapshot = timer.snapshot();
+        assert_eq!((snapshot.saved_at, snapshot.paused_seconds), (started_at + 3, 2));
+
+        // A minute passes with the app down
+        clock.advance(SECOND * 60);
+        let mut timer = Timer::from_snapshot(snapshot, clock.clone());
+        assert_eq!(timer.get_event(), event(TimerState::Paused, TimerSession::Working, 2, 0));
+        timer.resume();
+        run_for(&mut timer, &clock, 2);
+        assert_eq!(timer.get_event(), event(TimerState::Waiting, TimerSession::Working, 0, 0));
+
+        timer.next_session();
+        let record = &timer.finished[0];
+        assert_eq!((record.actual_seconds, record.paused_seconds), (65, 62));
+        assert_eq!((record.started_at, record.ended_at), (started_at, started_at + 65));
+    }
+
+    #[test]
+    fn sleeping_is_dealt_with_by_policy() {
+        let asleep = |policy: SuspendPolicy| {
+            let clock = ManualClock::new();
+            let mut timer = Timer::new(Settings { suspend_policy: policy, ..test_settings() }, clock.clone());
+            timer.prepare_start();
+            run_for(&mut timer, &clock, 1);
+            clock.sleep(SECOND * 120);
+            timer.check_for_gap(None);
+            assert_eq!(timer.get_event().gap, Some(TimeGap::Suspended { seconds: 120, policy: Some(policy) }));
+            (timer, clock)
+        };
+        // A session that ran down goes into history once it is moved on from
+        let recorded = |timer: &mut Timer<ManualClock>| -> Vec<_> {
+            timer.next_session();
+            timer.finished.iter().map(|r| (r.session, r.outcome, r.planned_seconds, r.actual_seconds, r.paused_seconds)).collect()
+        };
+
+        let (mut timer, clock) = asleep(SuspendPolicy::Pause);
+        assert_eq!(timer.get_event(), TimerEvent { gap: timer.gap, ..event(TimerState::Paused, TimerSession::Working, 2, 0) });
+        timer.resume();
+        run_for(&mut timer, &clock, 2);
+        assert_eq!(recorded(&mut timer), vec![(TimerSession::Working, SessionOutcome::Completed, 3, 123, 120)]);
+
+        let (mut timer, _) = asleep(SuspendPolicy::Count);
+        timer.tick();
+        assert_eq!(timer.get_state(), TimerState::Waiting);
+        assert_eq!(recorded(&mut timer), vec![(TimerSession::Working, SessionOutcome::Completed, 3, 121, 0)]);
+
+        // The break ends the sequence's first pass, so work starts the next one
+        let (mut timer, _) = asleep(SuspendPolicy::Break);
+        assert_eq!(timer.get_event(), TimerEvent { gap: timer.gap, ..event(TimerState::Waiting, TimerSession::Break, 0, 1) });
+        assert_eq!(recorded(&mut timer), vec![
+            (TimerSession::Working, SessionOutcome::Interrupted, 3, 1, 0),
+            (TimerSession::Break, SessionOutcome::Completed, 2, 120, 0),
+        ]);
+        assert_eq!((timer.get_session(), timer.get_remaining(), timer.get_cycles_complete()), (TimerSession::Working, 3, 0));
+    }
+
+    #[test]
+    fn skip_restart_and_stop_work_from_any_state() {
+        let clock = ManualClock::new();
+        let mut timer = started_timer(&clock);
+
+        run_for(&mut timer, &clock, 1);
+        timer.restart_session();
+        assert_eq!(timer.get_event(), event(TimerState::CountDown, TimerSession::Working, 3, 0));
+        run_for(&mut timer, &clock, 1);
+        timer.skip();
+        assert_eq!(timer.get_event(), event(TimerState::CountDown, TimerSession::Resting, 1, 0));
+
+        // From Waiting, the second work session is passed over and its cycle counted
+        run_for(&mut timer, &clock, 1);
+        timer.skip();
+        assert_eq!(timer.get_event(), event(TimerState::CountDown, TimerSession::Break, 2, 1));
+        run_for(&mut timer, &clock, 1);
+        timer.stop();
+        assert_eq!(timer.get_event(), event(TimerState::Idle, TimerSession::Break, 0, 0));
+
+        let outcomes: Vec<_> = timer.finished.iter().map(|r| (r.session, r.outcome, r.actual_seconds, r.cycle)).collect();
+        assert_eq!(outcomes, vec![
+            (TimerSession::Working, SessionOutcome::Aborted, 1, 0),
+            (TimerSession::Working, SessionOutcome::Skipped, 1, 0),
+            (TimerSession::Resting, SessionOutcome::Completed, 1, 0),
+            (TimerSession::Working, SessionOutcome::Skipped, 0, 1),
+            (TimerSession::Break, SessionOutcome::Aborted, 1, 1),
+        ]);
+    }
+
+    #[test]
+    fn spawned_timer_emits_full_cycle() {
+        use TimerSession::*;
+        use TimerState::*;
+
+        let clock = ManualClock::new();
+        let handle = Timer::spawn(test_settings(), clock.clone());
+        let next_event = || handle.evt_rx.recv_timeout(Duration::from_secs(5)).expect("timer thread stalled");
+        let mut events = vec![next_event()];
+
+        // Each step either lets a second pass or sends a command, then collects the resulting event
+        let steps = [None, None, None, Some(TimerCommand::Next), None, Some(TimerCommand::Next), None, None, None, Some(TimerCommand::Next), None, None, Some(TimerCommand::Next)];
+        for step in steps {
+            match step {
+                Some(cmd) => handle.cmd_tx.send(cmd).unwrap(),
+                None => clock.advance(SECOND),
+            }
+            events.push(next_event());
+        }
+
+        assert_eq!(events, vec![
+            event(CountDown, Working, 3, 0),
+            event(CountDown, Working, 2, 0),
+            event(CountDown, Working, 1, 0),
+            event(Waiting, Working, 0, 0),
+            event(CountDown, Resting, 1, 0),
+            event(Waiting, Resting, 0, 0),
+            event(CountDown, Working, 3, 1),
+            event(CountDown, Working, 2, 1),
+            event(CountDown, Working, 1, 1),
+            event(Waiting, Working, 0, 1),
+            event(CountDown, Break, 2, 1),
+            event(CountDown, Break, 1, 1),
+            event(Waiting, Break, 0, 1),
+            event(CountDown, Working, 3, 0),
+        ]);
+
+        // Waiting does not tick, and stop is accepted once paused
+        handle.cmd_tx.send(TimerCommand::Pause).unwrap();
+        assert_eq!(next_event(), event(Paused, Working, 3, 0));
+        clock.advance(SECOND * 5);
+        handle.cmd_tx.send(TimerCommand::Stop).unwrap();
+        assert!(handle.evt_rx.recv_timeout(Duration::from_secs(5)).is_err());
+    }
+}
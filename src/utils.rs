@@ -1,6 +1,7 @@
 use std::error::Error;
 use std::io::{self, Write};
 use std::time::Duration;
+use chrono::{Local, NaiveDate, NaiveTime};
 use crossterm::event::{poll, read, Event, KeyCode, KeyEvent};
 
 #[macro_export]
@@ -59,24 +60,125 @@ pub fn get_input(s1: &str) -> String {
 pub fn get_posint_input(s1: &str) -> u32 {
     loop {  
         let rs = get_input(s1).parse::<u32>();
-        match rs {
-            Ok(v) => if v > 0 {return v} ,
-            Err(_) => ()
+        if let Ok(v) = rs && v > 0 {
+            return v;
         }
         println!("Please enter a valid integer > 0!")
     }
 }
 
+pub fn get_uint_input(s1: &str) -> u32 {
+    loop {  
+        if let Ok(v) = get_input(s1).parse::<u32>() {
+            return v;
+        }
+        println!("Please enter a valid integer!")
+    }
+}
+
 pub fn clear_terminal() {
     print!("{}[2J", 27 as char); 
     io::stdout().flush().unwrap();          
 }
 
 pub fn poll_user_input() -> Option<KeyCode> {
-    if poll(Duration::from_millis(0)).ok()? {
-        if let Event::Key(KeyEvent {code, .. }) =  read().ok()? {
-            return Some(code);
-        } 
-    } 
+    if poll(Duration::from_millis(0)).ok()?
+        && let Event::Key(KeyEvent {code, .. }) =  read().ok()? {
+        return Some(code);
+    }
     None
-}
\ No newline at end of file
+}
+
+// Parse a human friendly duration into seconds, e.g. "25m", "20s", "1h30m" or "90". Bare numbers are seconds
+pub fn parse_duration(s: &str) -> Result<u32, String> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Err("Duration is empty".to_string());
+    }
+    if let Ok(seconds) = s.parse::<u32>() {
+        return Ok(seconds);
+    }
+
+    let mut total: u32 = 0;
+    let mut number = String::new();
+    for c in s.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let multiplier = match c {
+            'h' => 60 * 60,
+            'm' => 60,
+            's' => 1,
+            _ => return Err(format!("Unknown unit '{c}' in duration '{s}' (use h, m or s)")),
+        };
+        let value = number.parse::<u32>().map_err(|_| format!("Missing number before '{c}' in duration '{s}'"))?;
+        total = value.checked_mul(multiplier)
+            .and_then(|v| total.checked_add(v))
+            .ok_or_else(|| format!("Duration '{s}' is too long"))?;
+        number.clear();
+    }
+    if !number.is_empty() {
+        return Err(format!("Missing unit after '{number}' in duration '{s}'"));
+    }
+    Ok(total)
+}
+
+// Minutes and seconds as a clock shows them, e.g. 90 becomes "01:30"
+pub fn get_display_time(time_seconds: u32) -> String {
+    // Rust int division always truncates
+    format!("{:02}:{:02}", (time_seconds/60), time_seconds%60)
+}
+
+// The reverse of parse_duration, e.g. 5400 becomes "1h30m"
+pub fn format_duration(seconds: u32) -> String {
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    let parts = [(hours, 'h'), (minutes, 'm'), (seconds, 's')];
+    let text: String = parts.iter().filter(|(value, _)| *value > 0).map(|(value, unit)| format!("{value}{unit}")).collect();
+    if text.is_empty() { "0s".to_string() } else { text }
+}
+
+// Parse a YYYY-MM-DD date
+pub fn parse_date(s: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(s.trim(), "%Y-%m-%d")
+        .map_err(|_| format!("Invalid date '{s}', expected YYYY-MM-DD"))
+}
+
+// Parse a YYYY-MM-DD date into the Unix timestamp of local midnight at its start
+pub fn parse_local_date(s: &str) -> Result<i64, String> {
+    local_midnight(parse_date(s)?).ok_or_else(|| format!("Date '{s}' does not exist in the local timezone"))
+}
+
+// Unix timestamp of the start of a day in the local timezone
+pub fn local_midnight(date: NaiveDate) -> Option<i64> {
+    date.and_time(NaiveTime::MIN)
+        .and_local_timezone(Local)
+        .earliest()
+        .map(|time| time.timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_duration_accepts_units_and_bare_seconds() {
+        assert_eq!(parse_duration("90"), Ok(90));
+        assert_eq!(parse_duration("20s"), Ok(20));
+        assert_eq!(parse_duration("25m"), Ok(25 * 60));
+        assert_eq!(parse_duration("1h30m"), Ok(90 * 60));
+        assert_eq!(parse_duration(" 2m5s "), Ok(125));
+        for seconds in [0, 20, 1500, 5400, 3661] {
+            assert_eq!(parse_duration(&format_duration(seconds)), Ok(seconds));
+        }
+    }
+
+    #[test]
+    fn parse_duration_rejects_malformed_input() {
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("m").is_err());
+        assert!(parse_duration("5x").is_err());
+        assert!(parse_duration("1h30").is_err());
+        assert!(parse_duration("9999999h").is_err());
+    }
+}